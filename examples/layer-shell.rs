@@ -8,7 +8,10 @@ use linux_toolkit::wayland::mem_pool::{DoubleMemPool, MemPool};
 use linux_toolkit::wayland::pointer::PointerEvent;
 use linux_toolkit::wayland::seat::SeatEvent;
 use linux_toolkit::wayland::shm::Format;
-use linux_toolkit::wayland::surface::{SurfaceRequests, WlSurface};
+use linux_toolkit::wayland::surface::{
+    request_frame, request_redraw, set_buffer_size, take_redraw,
+    SurfaceRequests, WlSurface,
+};
 use linux_toolkit::wayland::toplevel_manager::{
     ToplevelEvent, ToplevelManager,
 };
@@ -25,8 +28,6 @@ fn main() {
     let output = environment
         .output_manager
         .outputs()
-        .lock()
-        .unwrap()
         .first()
         .unwrap()
         .clone();
@@ -42,7 +43,6 @@ fn main() {
     let mut configure = false;
     let mut resize = true;
     let mut surface_size = None;
-    let mut surface_scale_factor = 1;
 
     loop {
         layer_surface.poll_events(|event, _layer_surface| match event {
@@ -56,12 +56,13 @@ fn main() {
                     resize = true;
                 }
             }
-            LayerSurfaceEvent::Scale { scale_factor } => {
-                if scale_factor != surface_scale_factor {
-                    surface_scale_factor = scale_factor;
-                    resize = true;
-                }
+            LayerSurfaceEvent::Scale { .. }
+            | LayerSurfaceEvent::PreferredTransform { .. } => {
+                resize = true;
             }
+            LayerSurfaceEvent::Frame { .. }
+            | LayerSurfaceEvent::Presentation { .. }
+            | LayerSurfaceEvent::SubsurfaceSeat { .. } => {}
             LayerSurfaceEvent::Seat { seat_id: _, event } => {
                 if let SeatEvent::Pointer {
                     event: PointerEvent::Enter { ref cursor, .. },
@@ -93,17 +94,17 @@ fn main() {
         if close {
             break;
         }
-        if configure && resize {
+        if resize {
+            request_redraw(layer_surface.surface());
+            resize = false;
+        }
+        if configure {
             if let Some(pool) = pools.pool() {
-                redraw(
-                    pool,
-                    layer_surface.surface(),
-                    surface_size,
-                    surface_scale_factor,
-                )
-                .unwrap();
+                if take_redraw(layer_surface.surface()) {
+                    redraw(pool, layer_surface.surface(), surface_size)
+                        .unwrap();
+                }
             }
-            resize = false;
         }
         environment.handle_events();
     }
@@ -113,10 +114,9 @@ fn redraw(
     pool: &mut MemPool,
     surface: &Proxy<WlSurface>,
     size: Option<(u32, u32)>,
-    scale_factor: u32,
 ) -> Result<(), Error> {
     let size = size.unwrap_or((1024, 768));
-    let (width, height) = (size.0 * scale_factor, size.1 * scale_factor);
+    let (width, height) = set_buffer_size(surface, size);
 
     pool.resize((4 * width * height) as usize)?;
     pool.seek(SeekFrom::Start(0))?;
@@ -135,7 +135,7 @@ fn redraw(
         Format::Argb8888,
     );
     surface.attach(Some(&new_buffer), 0, 0);
-    surface.set_buffer_scale(scale_factor as i32);
+    request_frame(surface);
     surface.commit();
     Ok(())
 }
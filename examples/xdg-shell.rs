@@ -1,18 +1,27 @@
 use byteorder::{NativeEndian, WriteBytesExt};
-use linux_toolkit::wayland::clipboard::{Clipboard, ClipboardEvent};
+use linux_toolkit::mime::{self, Payload};
+use linux_toolkit::wayland::clipboard::{
+    Clipboard, ClipboardData, ClipboardEvent,
+};
+use linux_toolkit::wayland::damage::Rect;
 use linux_toolkit::wayland::data_device::DataDeviceEvent;
 use linux_toolkit::wayland::environment::Environment;
 use linux_toolkit::wayland::keyboard::{KeyState, KeyboardEvent};
 use linux_toolkit::wayland::mem_pool::{DoubleMemPool, MemPool};
-use linux_toolkit::wayland::output::OutputUserData;
-use linux_toolkit::wayland::pointer::PointerEvent;
-use linux_toolkit::wayland::seat::{SeatEvent, SeatUserData};
+use linux_toolkit::wayland::output::{OutputEvent, OutputUserData};
+use linux_toolkit::wayland::pointer::{ButtonState, MouseButton, PointerEvent};
+use linux_toolkit::wayland::seat::SeatEvent;
 use linux_toolkit::wayland::shm::Format;
-use linux_toolkit::wayland::surface::{SurfaceRequests, WlSurface};
-use linux_toolkit::wayland::xdg_shell::{XdgShell, XdgSurfaceEvent};
+use linux_toolkit::wayland::surface::{
+    request_frame, request_redraw, set_buffer_size, set_buffer_transform,
+    take_redraw, SurfaceRequests, SurfaceUserData, WlSurface,
+};
+use linux_toolkit::wayland::xdg_shell::{
+    resize_edge, resize_edge_cursor, ResizeEdge, XdgShell, XdgSurfaceEvent,
+};
 use linux_toolkit::wayland::xkbcommon::keysyms::KEY_Escape;
 use linux_toolkit::wayland::Proxy;
-use std::io::{BufWriter, Error, Read, Seek, SeekFrom, Write};
+use std::io::{BufWriter, Error, Seek, SeekFrom, Write};
 use std::sync::Mutex;
 
 fn main() {
@@ -21,6 +30,7 @@ fn main() {
     let xdg_shell = XdgShell::new(
         &environment.globals,
         environment.surface_manager.clone(),
+        environment.seat_manager.clone(),
     );
     let xdg_surface = xdg_shell.create_shell_surface();
     xdg_surface.set_app_id("com.example.XdgShellExample".into());
@@ -29,8 +39,8 @@ fn main() {
     let mut clipboard = Clipboard::new(
         environment.seat_manager.clone(),
         environment.data_source_manager.clone(),
-        vec!["text/plain;charset=utf-8".into()],
     );
+    let text_types = mime::text_mime_types();
 
     print_outputs(&environment);
     print_seats(&environment);
@@ -39,33 +49,61 @@ fn main() {
     let mut configure = false;
     let mut resize = true;
     let mut surface_size = None;
-    let mut surface_scale_factor = 1;
+    let mut pointer = None;
+    let mut pointer_edge = None;
 
     loop {
-        xdg_surface.poll_events(|event, _xdg_surface| match event {
+        xdg_surface.poll_events(|event, xdg_surface| match event {
             XdgSurfaceEvent::Close => {
                 close = true;
             }
             XdgSurfaceEvent::Configure { size, .. } => {
+                // redraw even if only the states changed, the configure
+                // is acked when the new buffer is committed
                 configure = true;
-                if surface_size != size {
-                    surface_size = size;
-                    resize = true;
-                }
+                surface_size = size;
+                resize = true;
             }
-            XdgSurfaceEvent::Scale { scale_factor } => {
-                if scale_factor != surface_scale_factor {
-                    surface_scale_factor = scale_factor;
-                    resize = true;
-                }
+            XdgSurfaceEvent::Scale { .. }
+            | XdgSurfaceEvent::PreferredTransform { .. } => {
+                resize = true;
             }
+            XdgSurfaceEvent::Frame { .. }
+            | XdgSurfaceEvent::Presentation { .. }
+            | XdgSurfaceEvent::SubsurfaceSeat { .. } => {}
             XdgSurfaceEvent::Seat { seat_id, event } => {
                 match &event {
                     SeatEvent::Pointer { event } => match event {
-                        PointerEvent::Enter { ref cursor, .. } => {
-                            cursor
-                                .change_cursor(Some("grabbing".into()))
-                                .unwrap();
+                        PointerEvent::Enter {
+                            ref cursor, x, y, ..
+                        } => {
+                            pointer = Some((cursor.clone(), (*x, *y)));
+                        }
+                        PointerEvent::Motion { x, y, .. } => {
+                            if let Some((_, ref mut position)) = pointer {
+                                *position = (*x, *y);
+                            }
+                        }
+                        PointerEvent::Leave { .. } => {
+                            pointer = None;
+                            pointer_edge = None;
+                        }
+                        PointerEvent::Button {
+                            button: MouseButton::Left,
+                            state: ButtonState::Pressed,
+                            serial,
+                            ..
+                        } => {
+                            if let Some((_, position)) = pointer {
+                                let size = surface_size.unwrap_or((1024, 768));
+                                match resize_edge(position, size, 8.0) {
+                                    ResizeEdge::None => {
+                                        xdg_surface.start_move(seat_id, *serial)
+                                    }
+                                    edge => xdg_surface
+                                        .start_resize(seat_id, *serial, edge),
+                                }
+                            }
                         }
                         _ => {}
                     },
@@ -82,10 +120,12 @@ fn main() {
                                     close = true;
                                 } else if *utf8 == Some("y".into()) {
                                     println!("set selection");
-                                    clipboard.set(seat_id, *serial);
+                                    let data = ClipboardData::new()
+                                        .with_text("hello world!");
+                                    clipboard.set(seat_id, *serial, data);
                                 } else if *utf8 == Some("p".into()) {
                                     println!("get selection");
-                                    clipboard.get(seat_id);
+                                    clipboard.get(seat_id, &text_types);
                                 }
                             }
                         }
@@ -110,41 +150,71 @@ fn main() {
         if close {
             break;
         }
-        if configure && resize {
-            if let Some(pool) = pools.pool() {
-                redraw(
-                    pool,
-                    xdg_surface.surface(),
-                    surface_size,
-                    surface_scale_factor,
-                )
-                .unwrap();
+        if let Some((ref cursor, position)) = pointer {
+            let size = surface_size.unwrap_or((1024, 768));
+            let edge = resize_edge(position, size, 8.0);
+            if pointer_edge != Some(edge) {
+                pointer_edge = Some(edge);
+                let name = resize_edge_cursor(edge);
+                cursor.change_cursor(Some(name.into())).unwrap();
             }
+        }
+        if resize {
+            request_redraw(xdg_surface.surface());
             resize = false;
         }
+        if configure {
+            if let Some(pool) = pools.pool() {
+                if take_redraw(xdg_surface.surface()) {
+                    redraw(pool, xdg_surface.surface(), surface_size).unwrap();
+                    let (width, height) = surface_size.unwrap_or((1024, 768));
+                    let geometry = Rect::new(0, 0, width as i32, height as i32);
+                    xdg_surface.set_window_geometry(geometry);
+                    xdg_surface.commit();
+                }
+            }
+        }
         environment.flush();
         clipboard.poll_events(|event| match event {
             ClipboardEvent::Get {
                 seat_id: _,
-                mut pipe,
-                mime_type: _,
+                mime_type,
+                data,
             } => {
-                let mut text = String::new();
-                pipe.read_to_string(&mut text).unwrap();
-                println!("selection: {}", text);
+                if let Payload::Text(text) = Payload::decode(&mime_type, data) {
+                    println!("selection: {}", text);
+                }
             }
             ClipboardEvent::GetLocal {
                 seat_id: _,
-                mime_type: _,
+                mime_type,
+                data,
             } => {
-                println!("local selection: hello world!");
+                if let Payload::Text(text) = Payload::decode(&mime_type, data) {
+                    println!("local selection: {}", text);
+                }
             }
-            ClipboardEvent::Set {
+            ClipboardEvent::Error {
                 seat_id: _,
-                mut pipe,
-                mime_type: _,
+                mime_type,
+                error,
             } => {
-                pipe.write(b"hello world!").unwrap();
+                println!(
+                    "clipboard transfer of {} failed: {}",
+                    mime_type, error
+                );
+            }
+            ClipboardEvent::Sent { .. } => {}
+        });
+        environment.output_manager.poll_events(|event| match event {
+            OutputEvent::Added { info, .. } => {
+                println!("output added: {:?}", info.name);
+            }
+            OutputEvent::Changed { info, changes, .. } => {
+                println!("output changed: {:?} {:?}", info.name, changes);
+            }
+            OutputEvent::Removed { info, .. } => {
+                println!("output removed: {:?}", info.name);
             }
         });
         environment.handle_events();
@@ -155,10 +225,16 @@ fn redraw(
     pool: &mut MemPool,
     surface: &Proxy<WlSurface>,
     size: Option<(u32, u32)>,
-    scale_factor: u32,
 ) -> Result<(), Error> {
     let size = size.unwrap_or((1024, 768));
-    let (width, height) = (size.0 * scale_factor, size.1 * scale_factor);
+    let transform = surface
+        .user_data::<Mutex<SurfaceUserData>>()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .preferred_transform();
+    set_buffer_transform(surface, transform);
+    let (width, height) = set_buffer_size(surface, size);
 
     pool.resize((4 * width * height) as usize)?;
     pool.seek(SeekFrom::Start(0))?;
@@ -177,13 +253,12 @@ fn redraw(
         Format::Argb8888,
     );
     surface.attach(Some(&new_buffer), 0, 0);
-    surface.set_buffer_scale(scale_factor as i32);
-    surface.commit();
+    request_frame(surface);
     Ok(())
 }
 
 fn print_outputs(environment: &Environment) {
-    let outputs = environment.output_manager.outputs().lock().unwrap();
+    let outputs = environment.output_manager.outputs();
 
     for output in outputs.iter() {
         let ud = output
@@ -196,14 +271,7 @@ fn print_outputs(environment: &Environment) {
 }
 
 fn print_seats(environment: &Environment) {
-    let seats = environment.seat_manager.seats().lock().unwrap();
-
-    for seat in seats.iter() {
-        let ud = seat
-            .user_data::<Mutex<SeatUserData>>()
-            .unwrap()
-            .lock()
-            .unwrap();
-        println!("{}", ud.name());
+    for seat in environment.seat_manager.seats() {
+        println!("{}: {} (v{})", seat.id(), seat.name(), seat.version());
     }
 }
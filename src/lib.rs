@@ -1,4 +1,5 @@
 //! A toolkit for modern linux desktop applications
 #![deny(missing_docs)]
 pub mod locale;
+pub mod mime;
 pub mod wayland;
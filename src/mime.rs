@@ -0,0 +1,397 @@
+//! MIME type codecs for clipboard and drag'n'drop payloads
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+/// UTF-8 encoded plain text
+pub const TEXT_PLAIN_UTF8: &str = "text/plain;charset=utf-8";
+/// Plain text in an unspecified encoding, usually UTF-8
+pub const TEXT_PLAIN: &str = "text/plain";
+/// Legacy X11 name of UTF-8 encoded plain text
+pub const UTF8_STRING: &str = "UTF8_STRING";
+/// Legacy X11 name of plain text in an unspecified encoding
+pub const TEXT: &str = "TEXT";
+/// Legacy X11 name of ISO-8859-1 encoded plain text
+pub const STRING: &str = "STRING";
+/// A list of URIs
+pub const TEXT_URI_LIST: &str = "text/uri-list";
+/// HTML markup
+pub const TEXT_HTML: &str = "text/html";
+/// Files copied or cut in a file manager
+pub const GNOME_COPIED_FILES: &str = "x-special/gnome-copied-files";
+
+/// The plain text mime types in order of preference
+pub const TEXT_MIME_TYPES: &[&str] =
+    &[TEXT_PLAIN_UTF8, UTF8_STRING, TEXT_PLAIN, TEXT, STRING];
+
+/// Returns the plain text mime types in order of preference
+///
+/// Pass them to `Clipboard::get` to paste text.
+pub fn text_mime_types() -> Vec<String> {
+    TEXT_MIME_TYPES
+        .iter()
+        .map(|mime| mime.to_string())
+        .collect()
+}
+
+/// Returns `true` if `mime_type` is a plain text mime type
+pub fn is_text(mime_type: &str) -> bool {
+    TEXT_MIME_TYPES.contains(&mime_type)
+        || mime_type.eq_ignore_ascii_case(TEXT_PLAIN_UTF8)
+}
+
+/// Encodes text to be sent as `mime_type`
+///
+/// `STRING` is encoded as ISO-8859-1, every other text mime type as UTF-8.
+/// Returns `None` if `mime_type` is not a plain text mime type or `text`
+/// can't be represented in ISO-8859-1.
+pub fn encode_text(mime_type: &str, text: &str) -> Option<Vec<u8>> {
+    if !is_text(mime_type) {
+        return None;
+    }
+    if mime_type == STRING {
+        text.chars()
+            .map(|c| {
+                if (c as u32) < 0x100 {
+                    Some(c as u8)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    } else {
+        Some(text.as_bytes().to_vec())
+    }
+}
+
+/// Decodes text received as `mime_type`
+///
+/// `STRING` is decoded as ISO-8859-1, every other text mime type as UTF-8
+/// replacing invalid sequences. Returns `None` if `mime_type` is not a
+/// plain text mime type.
+pub fn decode_text(mime_type: &str, data: &[u8]) -> Option<String> {
+    if !is_text(mime_type) {
+        return None;
+    }
+    // some X11 clients send a terminating nul byte
+    let data = match data.last() {
+        Some(0) => &data[..data.len() - 1],
+        _ => data,
+    };
+    if mime_type == STRING {
+        Some(data.iter().map(|b| *b as char).collect())
+    } else {
+        Some(String::from_utf8_lossy(data).into_owned())
+    }
+}
+
+/// Encodes HTML markup as UTF-8
+pub fn encode_html(html: &str) -> Vec<u8> {
+    html.as_bytes().to_vec()
+}
+
+/// Decodes HTML markup
+///
+/// Some browsers send UTF-16 with a byte order mark, otherwise UTF-8 is
+/// assumed.
+pub fn decode_html(data: &[u8]) -> String {
+    let utf16 = |data: &[u8], from: fn([u8; 2]) -> u16| {
+        let units = data
+            .chunks(2)
+            .filter(|chunk| chunk.len() == 2)
+            .map(|chunk| from([chunk[0], chunk[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16_lossy(&units)
+    };
+    if data.starts_with(&[0xFF, 0xFE]) {
+        utf16(&data[2..], u16::from_le_bytes)
+    } else if data.starts_with(&[0xFE, 0xFF]) {
+        utf16(&data[2..], u16::from_be_bytes)
+    } else if data.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        String::from_utf8_lossy(&data[3..]).into_owned()
+    } else {
+        String::from_utf8_lossy(data).into_owned()
+    }
+}
+
+/// Converts an absolute path to a `file://` URI
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.as_os_str().as_bytes() {
+        match *byte {
+            b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'/' => uri.push(*byte as char),
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Converts a `file://` URI to a path
+///
+/// Returns `None` for other schemes and for files on remote hosts.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    if !uri.starts_with("file://") {
+        return None;
+    }
+    let rest = &uri["file://".len()..];
+    let path = match rest.find('/') {
+        Some(0) => rest,
+        Some(i) if &rest[..i] == "localhost" => &rest[i..],
+        _ => return None,
+    };
+    let bytes = percent_decode(path)?;
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+fn percent_decode(s: &str) -> Option<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(decoded)
+}
+
+/// Encodes paths as a `text/uri-list`
+pub fn encode_uri_list(paths: &[PathBuf]) -> Vec<u8> {
+    let mut data = String::new();
+    for path in paths {
+        data.push_str(&path_to_uri(path));
+        data.push_str("\r\n");
+    }
+    data.into_bytes()
+}
+
+/// Decodes a `text/uri-list` into its URIs
+///
+/// Comments and empty lines are skipped.
+pub fn decode_uri_list(data: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_owned())
+        .collect()
+}
+
+/// Decodes a `text/uri-list` into the paths of its `file://` URIs
+pub fn decode_uri_list_paths(data: &[u8]) -> Vec<PathBuf> {
+    decode_uri_list(data)
+        .iter()
+        .filter_map(|uri| uri_to_path(uri))
+        .collect()
+}
+
+/// The operation of `x-special/gnome-copied-files`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileOperation {
+    /// The files were copied
+    Copy,
+    /// The files were cut and should be moved on paste
+    Cut,
+}
+
+/// Encodes files as `x-special/gnome-copied-files`
+pub fn encode_gnome_copied_files(
+    operation: FileOperation,
+    paths: &[PathBuf],
+) -> Vec<u8> {
+    let mut data = String::from(match operation {
+        FileOperation::Copy => "copy",
+        FileOperation::Cut => "cut",
+    });
+    for path in paths {
+        data.push('\n');
+        data.push_str(&path_to_uri(path));
+    }
+    data.into_bytes()
+}
+
+/// Decodes `x-special/gnome-copied-files`
+pub fn decode_gnome_copied_files(
+    data: &[u8],
+) -> Option<(FileOperation, Vec<PathBuf>)> {
+    let data = String::from_utf8_lossy(data);
+    let mut lines = data.lines();
+    let operation = match lines.next()?.trim() {
+        "copy" => FileOperation::Copy,
+        "cut" => FileOperation::Cut,
+        _ => return None,
+    };
+    let paths = lines.filter_map(|uri| uri_to_path(uri.trim())).collect();
+    Some((operation, paths))
+}
+
+/// An image format detected by its magic bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    /// Portable Network Graphics
+    Png,
+    /// JPEG
+    Jpeg,
+    /// Graphics Interchange Format
+    Gif,
+    /// Windows bitmap
+    Bmp,
+    /// WebP
+    Webp,
+    /// Tagged Image File Format
+    Tiff,
+}
+
+impl ImageFormat {
+    /// The mime type of the image format
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Bmp => "image/bmp",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Tiff => "image/tiff",
+        }
+    }
+
+    /// The image format of `mime_type`
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "image/png" => Some(ImageFormat::Png),
+            "image/jpeg" | "image/jpg" => Some(ImageFormat::Jpeg),
+            "image/gif" => Some(ImageFormat::Gif),
+            "image/bmp" | "image/x-bmp" => Some(ImageFormat::Bmp),
+            "image/webp" => Some(ImageFormat::Webp),
+            "image/tiff" => Some(ImageFormat::Tiff),
+            _ => None,
+        }
+    }
+
+    /// Detects the image format of `data` by its magic bytes
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        let magic: &[(&[u8], ImageFormat)] = &[
+            (b"\x89PNG\r\n\x1A\n", ImageFormat::Png),
+            (b"\xFF\xD8\xFF", ImageFormat::Jpeg),
+            (b"GIF87a", ImageFormat::Gif),
+            (b"GIF89a", ImageFormat::Gif),
+            (b"BM", ImageFormat::Bmp),
+            (b"II*\x00", ImageFormat::Tiff),
+            (b"MM\x00*", ImageFormat::Tiff),
+        ];
+        if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP"
+        {
+            return Some(ImageFormat::Webp);
+        }
+        magic
+            .iter()
+            .find(|(bytes, _)| data.starts_with(bytes))
+            .map(|(_, format)| *format)
+    }
+}
+
+/// A decoded payload
+#[derive(Clone, Debug, PartialEq)]
+pub enum Payload {
+    /// Plain text
+    Text(String),
+    /// HTML markup
+    Html(String),
+    /// A `text/uri-list`
+    Uris(Vec<String>),
+    /// Files copied or cut in a file manager
+    Files(FileOperation, Vec<PathBuf>),
+    /// An image with a known format
+    Image(ImageFormat, Vec<u8>),
+    /// Data of an unknown mime type
+    Other(Vec<u8>),
+}
+
+impl Payload {
+    /// Decodes `data` received as `mime_type`
+    ///
+    /// Images are detected by their magic bytes, so a wrongly labeled
+    /// image is still recognized.
+    pub fn decode(mime_type: &str, data: Vec<u8>) -> Self {
+        if is_text(mime_type) {
+            return Payload::Text(decode_text(mime_type, &data).unwrap());
+        }
+        match mime_type {
+            TEXT_HTML => Payload::Html(decode_html(&data)),
+            TEXT_URI_LIST => Payload::Uris(decode_uri_list(&data)),
+            GNOME_COPIED_FILES => match decode_gnome_copied_files(&data) {
+                Some((operation, paths)) => Payload::Files(operation, paths),
+                None => Payload::Other(data),
+            },
+            _ => match ImageFormat::detect(&data) {
+                Some(format) => Payload::Image(format, data),
+                None => Payload::Other(data),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uri_round_trip() {
+        let path = Path::new("/home/user/a file/ä%.txt");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///home/user/a%20file/%C3%A4%25.txt");
+        assert_eq!(uri_to_path(&uri).unwrap(), path);
+        assert_eq!(
+            uri_to_path("file://localhost/tmp/x").unwrap(),
+            Path::new("/tmp/x")
+        );
+        assert_eq!(uri_to_path("file://host/tmp/x"), None);
+        assert_eq!(uri_to_path("http://host/x"), None);
+    }
+
+    #[test]
+    fn percent_decode_invalid() {
+        assert_eq!(percent_decode("a%41").unwrap(), b"aA");
+        assert_eq!(percent_decode("a%4"), None);
+        assert_eq!(percent_decode("a%zz"), None);
+    }
+
+    #[test]
+    fn text_nul_stripping() {
+        assert_eq!(decode_text(TEXT_PLAIN, b"abc\0").unwrap(), "abc");
+        assert_eq!(decode_text(TEXT_PLAIN, b"abc").unwrap(), "abc");
+        assert_eq!(decode_text(TEXT_HTML, b"abc"), None);
+    }
+
+    #[test]
+    fn text_latin1() {
+        let data = encode_text(STRING, "äb").unwrap();
+        assert_eq!(data, b"\xE4b");
+        assert_eq!(decode_text(STRING, &data).unwrap(), "äb");
+        assert_eq!(encode_text(STRING, "€"), None);
+        assert_eq!(encode_text(UTF8_STRING, "€").unwrap(), "€".as_bytes());
+    }
+
+    #[test]
+    fn image_detect() {
+        let png = b"\x89PNG\r\n\x1A\n\x00";
+        assert_eq!(ImageFormat::detect(png), Some(ImageFormat::Png));
+        let webp = b"RIFF\x00\x00\x00\x00WEBPVP8 ";
+        assert_eq!(ImageFormat::detect(webp), Some(ImageFormat::Webp));
+        assert_eq!(ImageFormat::detect(b"GIF89a"), Some(ImageFormat::Gif));
+        assert_eq!(ImageFormat::detect(b"RIFF"), None);
+        assert_eq!(ImageFormat::detect(b""), None);
+    }
+}
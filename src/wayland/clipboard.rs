@@ -1,15 +1,155 @@
 //! Wayland clipboard handling
+use crate::mime;
 use crate::wayland::data_source::{DataSourceEvent, DataSourceManager};
 use crate::wayland::event_queue::{EventDrain, EventQueue, EventSource};
-use crate::wayland::pipe::{ReadPipe, WritePipe};
+use crate::wayland::pipe::{RawFd, ReadPipe};
 use crate::wayland::seat::SeatManager;
+use crate::wayland::transfer::{TransferEngine, TransferEvent, TransferId};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The content of a single clipboard representation
+#[derive(Clone)]
+pub enum ClipboardContent {
+    /// The data is known up front
+    Data(Arc<Vec<u8>>),
+    /// The data is produced when it is requested
+    ///
+    /// The provider is called with the requested mime type.
+    Provider(Arc<Fn(&str) -> Vec<u8> + Send + Sync>),
+}
+
+impl ClipboardContent {
+    /// Returns the data of the content in `mime_type`
+    pub fn data(&self, mime_type: &str) -> Vec<u8> {
+        match self {
+            ClipboardContent::Data(data) => data.as_ref().clone(),
+            ClipboardContent::Provider(provider) => provider(mime_type),
+        }
+    }
+}
+
+/// The representations of a clipboard content
+///
+/// A copy can offer the same content in different formats, for example
+/// as `text/html` and as `text/plain;charset=utf-8`. The pasting client
+/// chooses the one it understands best.
+#[derive(Clone, Default)]
+pub struct ClipboardData {
+    representations: Vec<(String, ClipboardContent)>,
+}
+
+impl ClipboardData {
+    /// Creates an empty `ClipboardData`
+    pub fn new() -> Self {
+        ClipboardData {
+            representations: Vec::new(),
+        }
+    }
+
+    /// Adds a representation with a known content
+    pub fn with_data(self, mime_type: String, data: Vec<u8>) -> Self {
+        self.with_content(mime_type, ClipboardContent::Data(Arc::new(data)))
+    }
+
+    /// Adds a representation that is produced when it is requested
+    pub fn with_provider<F>(self, mime_type: String, provider: F) -> Self
+    where
+        F: Fn(&str) -> Vec<u8> + Send + Sync + 'static,
+    {
+        let content = ClipboardContent::Provider(Arc::new(provider));
+        self.with_content(mime_type, content)
+    }
+
+    /// Adds `text` as all the plain text mime types
+    ///
+    /// `STRING` is skipped if `text` can't be represented in ISO-8859-1.
+    pub fn with_text(self, text: &str) -> Self {
+        mime::TEXT_MIME_TYPES.iter().fold(self, |data, mime_type| {
+            match mime::encode_text(mime_type, text) {
+                Some(encoded) => data.with_data(mime_type.to_string(), encoded),
+                None => data,
+            }
+        })
+    }
+
+    /// Adds `html` as `text/html`
+    pub fn with_html(self, html: &str) -> Self {
+        self.with_data(mime::TEXT_HTML.to_owned(), mime::encode_html(html))
+    }
+
+    /// Adds `paths` as `text/uri-list` and `x-special/gnome-copied-files`
+    pub fn with_files(
+        self,
+        operation: mime::FileOperation,
+        paths: &[PathBuf],
+    ) -> Self {
+        self.with_data(
+            mime::TEXT_URI_LIST.to_owned(),
+            mime::encode_uri_list(paths),
+        )
+        .with_data(
+            mime::GNOME_COPIED_FILES.to_owned(),
+            mime::encode_gnome_copied_files(operation, paths),
+        )
+    }
+
+    /// Adds an encoded image with the mime type detected from its magic
+    /// bytes
+    ///
+    /// Fails if the image format is unknown.
+    pub fn with_image(self, image: Vec<u8>) -> Result<Self, ()> {
+        let format = mime::ImageFormat::detect(&image).ok_or(())?;
+        Ok(self.with_data(format.mime_type().to_owned(), image))
+    }
+
+    /// Adds a representation
+    ///
+    /// Representations are offered in the order they were added, adding
+    /// a mime type twice replaces the previous content.
+    pub fn with_content(
+        mut self,
+        mime_type: String,
+        content: ClipboardContent,
+    ) -> Self {
+        self.representations.retain(|(mime, _)| *mime != mime_type);
+        self.representations.push((mime_type, content));
+        self
+    }
+
+    /// The offered mime types
+    pub fn mime_types(&self) -> Vec<String> {
+        self.representations
+            .iter()
+            .map(|(mime_type, _)| mime_type.clone())
+            .collect()
+    }
+
+    /// Returns the content of the representation with `mime_type`
+    pub fn content(&self, mime_type: &str) -> Option<&ClipboardContent> {
+        self.representations
+            .iter()
+            .find(|(mime, _)| mime == mime_type)
+            .map(|(_, content)| content)
+    }
+
+    /// Returns the first mime type of `preferences` that is offered
+    pub fn negotiate(&self, preferences: &[String]) -> Option<String> {
+        preferences
+            .iter()
+            .find(|mime_type| self.content(mime_type).is_some())
+            .cloned()
+    }
+}
 
 /// Clipboard abstraction
 pub struct Clipboard {
     seat_manager: SeatManager,
     data_source_manager: DataSourceManager,
-    mime_types: Vec<String>,
-    data_sources: Vec<(u32, EventDrain<DataSourceEvent>)>,
+    data_sources: Vec<(u32, ClipboardData, EventDrain<DataSourceEvent>)>,
+    transfer_engine: TransferEngine,
+    transfers: Vec<(TransferId, u32, String)>,
     event_source: EventSource<ClipboardEvent>,
     event_drain: EventDrain<ClipboardEvent>,
 }
@@ -19,14 +159,14 @@ impl Clipboard {
     pub fn new(
         seat_manager: SeatManager,
         data_source_manager: DataSourceManager,
-        mime_types: Vec<String>,
     ) -> Self {
         let (event_source, event_drain) = EventQueue::new();
         Clipboard {
             seat_manager,
             data_source_manager,
-            mime_types,
             data_sources: Vec::new(),
+            transfer_engine: TransferEngine::new(),
+            transfers: Vec::new(),
             event_source,
             event_drain,
         }
@@ -34,73 +174,113 @@ impl Clipboard {
 
     /// Set clipboard content
     ///
-    /// Notifies the compositor that the clipboard has been updated.
-    /// When a wayland client requests the clipboard contents a
-    /// ClipboardEvent::Set will be emitted.
-    pub fn set(&mut self, seat_id: u32, serial: u32) {
+    /// Notifies the compositor that the clipboard has been updated and
+    /// offers all representations of `data`. When a wayland client requests
+    /// the clipboard contents the requested representation is sent to it.
+    pub fn set(&mut self, seat_id: u32, serial: u32, data: ClipboardData) {
         let data_device = self.seat_manager.get_data_device(seat_id).unwrap();
         let (data_source, drain) = self
             .data_source_manager
-            .create_data_source(&self.mime_types)
+            .create_data_source(&data.mime_types())
             .split();
         data_device.set_selection(Some(&data_source), serial);
-        self.data_sources.push((seat_id, drain));
+        self.data_sources.push((seat_id, data, drain));
     }
 
     /// Get the clipboard contents
     ///
-    /// If the clipboard isn't empty it will emit a ClipboardEvent::Get when
-    /// the wayland client setting the clipboard is ready to send the contents.
-    pub fn get(&self, seat_id: u32) {
-        if self
+    /// Picks the first mime type of `preferences` that the clipboard offers,
+    /// use `mime::text_mime_types` to paste text. Decode the received data
+    /// with `mime::Payload::decode`.
+    ///
+    /// If the clipboard isn't empty it will emit a ClipboardEvent::Get once
+    /// the contents were received from the wayland client setting the
+    /// clipboard.
+    ///
+    /// If the clipboard is owned by this `Clipboard` a ClipboardEvent::GetLocal
+    /// with the data is emitted instead.
+    pub fn get(&mut self, seat_id: u32, preferences: &[String]) {
+        if let Some((_, data, _)) = self
             .data_sources
             .iter()
-            .find(|(id, _)| *id == seat_id)
-            .is_some()
+            .rev()
+            .find(|(id, _, _)| *id == seat_id)
         {
-            let mime_type = self.mime_types[0].clone();
-            let event = ClipboardEvent::GetLocal { seat_id, mime_type };
-            self.event_source.push_event(event);
+            if let Some(mime_type) = data.negotiate(preferences) {
+                let data = data.content(&mime_type).unwrap().data(&mime_type);
+                let event = ClipboardEvent::GetLocal {
+                    seat_id,
+                    mime_type,
+                    data,
+                };
+                self.event_source.push_event(event);
+            }
             return;
         }
         let data_device = self.seat_manager.get_data_device(seat_id).unwrap();
-        let clipboard_types = &self.mime_types;
         if let Some(offer) = data_device.get_selection() {
             if let Some(mime_type) = offer.with_mime_types(|offer_types| {
-                for clipboard_type in clipboard_types {
-                    for offer_type in offer_types {
-                        if clipboard_type == offer_type {
-                            return Some(clipboard_type);
-                        }
-                    }
-                }
-                None
+                preferences
+                    .iter()
+                    .find(|mime_type| offer_types.contains(mime_type))
+                    .cloned()
             }) {
                 if let Some(pipe) = offer.receive(mime_type.clone()).ok() {
-                    let mime_type = mime_type.clone();
-                    let event = ClipboardEvent::Get {
-                        seat_id,
-                        pipe,
-                        mime_type,
-                    };
-                    self.event_source.push_event(event);
+                    self.read(seat_id, mime_type, pipe);
                 }
             }
         }
     }
 
+    fn read(&mut self, seat_id: u32, mime_type: String, pipe: ReadPipe) {
+        match self.transfer_engine.read(pipe) {
+            Ok(id) => self.transfers.push((id, seat_id, mime_type)),
+            Err(error) => {
+                let event = ClipboardEvent::Error {
+                    seat_id,
+                    mime_type,
+                    error,
+                };
+                self.event_source.push_event(event);
+            }
+        }
+    }
+
+    /// The file descriptors of pending transfers
+    ///
+    /// Clipboard contents are transferred in chunks whenever `poll_events`
+    /// is called. Add these to your event loop to call it as soon as a
+    /// transfer can make progress.
+    pub fn fds(&self) -> Vec<RawFd> {
+        self.transfer_engine.fds()
+    }
+
     /// Polls the clipboard event queue
+    ///
+    /// Also sends the clipboard contents to clients that requested them
+    /// and advances pending transfers without blocking.
     pub fn poll_events<F: FnMut(ClipboardEvent)>(&mut self, mut cb: F) {
-        self.data_sources.retain(|(seat_id, drain)| {
+        let transfer_engine = &mut self.transfer_engine;
+        let transfers = &mut self.transfers;
+        let event_source = &self.event_source;
+        self.data_sources.retain(|(seat_id, data, drain)| {
             let mut retain = true;
             drain.poll_events(|event| match event {
                 DataSourceEvent::Send { pipe, mime_type } => {
-                    let event = ClipboardEvent::Set {
-                        seat_id: *seat_id,
-                        pipe,
-                        mime_type,
+                    let data = match data.content(&mime_type) {
+                        Some(content) => content.data(&mime_type),
+                        None => return,
                     };
-                    cb(event);
+                    match transfer_engine.write(pipe, data) {
+                        Ok(id) => transfers.push((id, *seat_id, mime_type)),
+                        Err(error) => {
+                            event_source.push_event(ClipboardEvent::Error {
+                                seat_id: *seat_id,
+                                mime_type,
+                                error,
+                            });
+                        }
+                    }
                 }
                 DataSourceEvent::Cancelled {} => {
                     retain = false;
@@ -109,6 +289,32 @@ impl Clipboard {
             });
             retain
         });
+        let transfers = &mut self.transfers;
+        self.transfer_engine.dispatch(0, |event| {
+            let id = match event {
+                TransferEvent::Read { id, .. }
+                | TransferEvent::Written { id }
+                | TransferEvent::Error { id, .. } => id,
+            };
+            let position = transfers.iter().position(|(id2, _, _)| *id2 == id);
+            let (_, seat_id, mime_type) = transfers.remove(position.unwrap());
+            let event = match event {
+                TransferEvent::Read { data, .. } => ClipboardEvent::Get {
+                    seat_id,
+                    mime_type,
+                    data,
+                },
+                TransferEvent::Written { .. } => {
+                    ClipboardEvent::Sent { seat_id, mime_type }
+                }
+                TransferEvent::Error { error, .. } => ClipboardEvent::Error {
+                    seat_id,
+                    mime_type,
+                    error,
+                },
+            };
+            event_source.push_event(event);
+        });
         self.event_drain.poll_events(|event| {
             cb(event);
         });
@@ -117,29 +323,38 @@ impl Clipboard {
 
 /// Events emitted by `Clipboard`
 pub enum ClipboardEvent {
-    /// The clipboard contents are ready
+    /// The clipboard contents were received
     Get {
         /// The seat id of the clipboard
         seat_id: u32,
-        /// The read pipe
-        pipe: ReadPipe,
         /// The negotiated mime type
         mime_type: String,
+        /// The clipboard contents
+        data: Vec<u8>,
     },
-    /// A client has requested the clipboard contents
-    Set {
+    /// You requested your own clipboard contents
+    GetLocal {
         /// The seat id of the clipboard
         seat_id: u32,
-        /// The write pipe
-        pipe: WritePipe,
         /// The negotiated mime type
         mime_type: String,
+        /// The clipboard contents
+        data: Vec<u8>,
     },
-    /// You requested your own clipboard contents
-    GetLocal {
+    /// The clipboard contents were sent to a client that requested them
+    Sent {
         /// The seat id of the clipboard
         seat_id: u32,
-        /// The negotiated mime type
+        /// The requested mime type
+        mime_type: String,
+    },
+    /// Receiving or sending the clipboard contents failed
+    Error {
+        /// The seat id of the clipboard
+        seat_id: u32,
+        /// The mime type of the transfer
         mime_type: String,
+        /// The error
+        error: io::Error,
     },
 }
@@ -280,8 +280,6 @@ impl CursorManager {
         let new_scale_factor = self
             .output_manager
             .outputs()
-            .lock()
-            .unwrap()
             .iter()
             .map(|output| {
                 output
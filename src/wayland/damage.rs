@@ -0,0 +1,378 @@
+//! Damage tracking for partial redraws
+use crate::wayland::output::Transform;
+use crate::wayland::surface::{SurfaceRequests, WlSurface};
+use wayland_client::Proxy;
+
+/// More rectangles than this are merged into their bounding box
+const MAX_RECTS: usize = 16;
+
+/// A rectangle
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    /// The x coordinate of the top left corner
+    pub x: i32,
+    /// The y coordinate of the top left corner
+    pub y: i32,
+    /// The width
+    pub width: i32,
+    /// The height
+    pub height: i32,
+}
+
+impl Rect {
+    /// Creates a new `Rect`
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Creates a `Rect` from two opposite corners
+    fn from_corners((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> Self {
+        let (x, y) = (x1.min(x2), y1.min(y2));
+        Rect::new(x, y, x1.max(x2) - x, y1.max(y2) - y)
+    }
+
+    /// Returns `true` if the rectangle has no area
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    /// The area of the rectangle
+    pub fn area(&self) -> i64 {
+        if self.is_empty() {
+            0
+        } else {
+            i64::from(self.width) * i64::from(self.height)
+        }
+    }
+
+    /// The smallest rectangle containing both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Rect::from_corners(
+            (self.x.min(other.x), self.y.min(other.y)),
+            (
+                (self.x + self.width).max(other.x + other.width),
+                (self.y + self.height).max(other.y + other.height),
+            ),
+        )
+    }
+
+    /// The overlap of both rectangles
+    ///
+    /// Is empty if they don't overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let width = (self.x + self.width).min(other.x + other.width) - x;
+        let height = (self.y + self.height).min(other.y + other.height) - y;
+        if width <= 0 || height <= 0 {
+            Rect::default()
+        } else {
+            Rect::new(x, y, width, height)
+        }
+    }
+
+    /// Returns `true` if `other` lies completely inside the rectangle
+    pub fn contains(&self, other: &Rect) -> bool {
+        self.intersection(other) == *other
+    }
+}
+
+/// Returns `true` if `transform` swaps width and height
+fn is_rotated(transform: Transform) -> bool {
+    match transform {
+        Transform::_90
+        | Transform::_270
+        | Transform::Flipped90
+        | Transform::Flipped270 => true,
+        _ => false,
+    }
+}
+
+/// The transform undoing `transform`
+fn invert(transform: Transform) -> Transform {
+    match transform {
+        Transform::_90 => Transform::_270,
+        Transform::_270 => Transform::_90,
+        transform => transform,
+    }
+}
+
+/// Applies `transform` to a point in a space of `size`
+fn transform_point(
+    (x, y): (i32, i32),
+    (width, height): (i32, i32),
+    transform: Transform,
+) -> (i32, i32) {
+    match transform {
+        Transform::_90 => (y, width - x),
+        Transform::_180 => (width - x, height - y),
+        Transform::_270 => (height - y, x),
+        Transform::Flipped => (width - x, y),
+        Transform::Flipped90 => (y, x),
+        Transform::Flipped180 => (x, height - y),
+        Transform::Flipped270 => (height - y, width - x),
+        _ => (x, y),
+    }
+}
+
+/// Applies `transform` to a rectangle in a space of `size`
+fn transform_rect(rect: Rect, size: (i32, i32), transform: Transform) -> Rect {
+    Rect::from_corners(
+        transform_point((rect.x, rect.y), size, transform),
+        transform_point(
+            (rect.x + rect.width, rect.y + rect.height),
+            size,
+            transform,
+        ),
+    )
+}
+
+/// Accumulates the damaged parts of a surface between commits
+///
+/// Rectangles are stored in buffer coordinates and merged when that
+/// doesn't damage much more than requested.
+#[derive(Clone, Debug)]
+pub struct Damage {
+    buffer_size: (i32, i32),
+    scale: i32,
+    transform: Transform,
+    rects: Vec<Rect>,
+}
+
+impl Damage {
+    /// Creates a new `Damage` for a buffer of `buffer_size` pixels
+    ///
+    /// `scale` and `transform` are the buffer scale and buffer transform
+    /// of the surface.
+    pub fn new(
+        buffer_size: (i32, i32),
+        scale: i32,
+        transform: Transform,
+    ) -> Self {
+        Damage {
+            buffer_size,
+            scale: scale.max(1),
+            transform,
+            rects: Vec::new(),
+        }
+    }
+
+    /// Changes the buffer size, scale or transform
+    ///
+    /// Damages the whole buffer if anything changed.
+    pub fn set_buffer(
+        &mut self,
+        buffer_size: (i32, i32),
+        scale: i32,
+        transform: Transform,
+    ) {
+        let scale = scale.max(1);
+        if self.buffer_size != buffer_size
+            || self.scale != scale
+            || self.transform != transform
+        {
+            self.buffer_size = buffer_size;
+            self.scale = scale;
+            self.transform = transform;
+            self.add_all();
+        }
+    }
+
+    /// The size of the surface in surface coordinates
+    pub fn surface_size(&self) -> (i32, i32) {
+        let (width, height) = self.buffer_size;
+        let size = (width / self.scale, height / self.scale);
+        if is_rotated(self.transform) {
+            (size.1, size.0)
+        } else {
+            size
+        }
+    }
+
+    /// Converts a rectangle from surface to buffer coordinates
+    pub fn surface_to_buffer(&self, rect: Rect) -> Rect {
+        let rect = transform_rect(rect, self.surface_size(), self.transform);
+        Rect::new(
+            rect.x * self.scale,
+            rect.y * self.scale,
+            rect.width * self.scale,
+            rect.height * self.scale,
+        )
+    }
+
+    /// Converts a rectangle from buffer to surface coordinates
+    ///
+    /// The result is rounded outwards to whole surface coordinates.
+    pub fn buffer_to_surface(&self, rect: Rect) -> Rect {
+        let scale = self.scale;
+        let floor = |value: i32| (value as f64 / scale as f64).floor() as i32;
+        let ceil = |value: i32| (value as f64 / scale as f64).ceil() as i32;
+        let rect = Rect::from_corners(
+            (floor(rect.x), floor(rect.y)),
+            (ceil(rect.x + rect.width), ceil(rect.y + rect.height)),
+        );
+        let (width, height) = self.buffer_size;
+        let size = (width / scale, height / scale);
+        transform_rect(rect, size, invert(self.transform))
+    }
+
+    /// Damages a rectangle in surface coordinates
+    pub fn add_surface(&mut self, rect: Rect) {
+        let rect = self.surface_to_buffer(rect);
+        self.add_buffer(rect);
+    }
+
+    /// Damages a rectangle in buffer coordinates
+    pub fn add_buffer(&mut self, rect: Rect) {
+        let (width, height) = self.buffer_size;
+        let mut rect = rect.intersection(&Rect::new(0, 0, width, height));
+        if rect.is_empty() {
+            return;
+        }
+        // merge with every rectangle where the bounding box wastes no area
+        loop {
+            let position = self.rects.iter().position(|rect2| {
+                rect.union(rect2).area()
+                    <= rect.area() + rect2.area()
+                        - rect.intersection(rect2).area()
+            });
+            match position {
+                Some(position) => {
+                    rect = rect.union(&self.rects.swap_remove(position));
+                }
+                None => break,
+            }
+        }
+        self.rects.push(rect);
+        if self.rects.len() > MAX_RECTS {
+            let bounds = self
+                .rects
+                .iter()
+                .fold(Rect::default(), |bounds, rect| bounds.union(rect));
+            self.rects = vec![bounds];
+        }
+    }
+
+    /// Damages the whole buffer
+    pub fn add_all(&mut self) {
+        let (width, height) = self.buffer_size;
+        self.rects.clear();
+        self.add_buffer(Rect::new(0, 0, width, height));
+    }
+
+    /// Returns `true` if nothing is damaged
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// The damaged rectangles in buffer coordinates
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// Sends the damage to `surface` and clears it
+    ///
+    /// Uses `damage_buffer` if `surface` supports it and falls back to
+    /// `damage` in surface coordinates otherwise. Call it before
+    /// committing.
+    pub fn apply(&mut self, surface: &Proxy<WlSurface>) {
+        for rect in &self.rects {
+            if surface.version() >= 4 {
+                surface.damage_buffer(rect.x, rect.y, rect.width, rect.height);
+            } else {
+                let rect = self.buffer_to_surface(*rect);
+                surface.damage(rect.x, rect.y, rect.width, rect.height);
+            }
+        }
+        self.rects.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSFORMS: [Transform; 8] = [
+        Transform::Normal,
+        Transform::_90,
+        Transform::_180,
+        Transform::_270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    #[test]
+    fn surface_buffer_round_trip() {
+        let rect = Rect::new(3, 7, 11, 5);
+        for transform in TRANSFORMS.iter().cloned() {
+            let damage = Damage::new((200, 120), 2, transform);
+            let buffer = damage.surface_to_buffer(rect);
+            assert_eq!(buffer.area(), rect.area() * 4);
+            assert!(Rect::new(0, 0, 200, 120).contains(&buffer));
+            assert_eq!(damage.buffer_to_surface(buffer), rect);
+        }
+    }
+
+    #[test]
+    fn surface_to_buffer_rotated() {
+        let damage = Damage::new((200, 120), 2, Transform::_90);
+        assert_eq!(damage.surface_size(), (60, 100));
+        assert_eq!(
+            damage.surface_to_buffer(Rect::new(0, 0, 10, 20)),
+            Rect::new(0, 100, 40, 20)
+        );
+    }
+
+    #[test]
+    fn buffer_to_surface_rounds_outwards() {
+        let damage = Damage::new((200, 120), 2, Transform::Normal);
+        assert_eq!(
+            damage.buffer_to_surface(Rect::new(3, 3, 2, 2)),
+            Rect::new(1, 1, 2, 2)
+        );
+    }
+
+    #[test]
+    fn add_buffer_merges() {
+        let mut damage = Damage::new((100, 100), 1, Transform::Normal);
+        damage.add_buffer(Rect::new(0, 0, 10, 10));
+        damage.add_buffer(Rect::new(10, 0, 10, 10));
+        assert_eq!(damage.rects(), &[Rect::new(0, 0, 20, 10)]);
+        damage.add_buffer(Rect::new(5, 5, 5, 5));
+        assert_eq!(damage.rects(), &[Rect::new(0, 0, 20, 10)]);
+        damage.add_buffer(Rect::new(50, 50, 10, 10));
+        assert_eq!(damage.rects().len(), 2);
+        damage.add_buffer(Rect::new(0, 10, 60, 50));
+        assert_eq!(
+            damage.rects(),
+            &[Rect::new(0, 0, 20, 10), Rect::new(0, 10, 60, 50)]
+        );
+        damage.add_buffer(Rect::new(20, 0, 40, 10));
+        assert_eq!(damage.rects(), &[Rect::new(0, 0, 60, 60)]);
+    }
+
+    #[test]
+    fn add_buffer_clips_and_collapses() {
+        let mut damage = Damage::new((100, 100), 1, Transform::Normal);
+        damage.add_buffer(Rect::new(-5, -5, 10, 10));
+        damage.add_buffer(Rect::new(100, 0, 10, 10));
+        assert_eq!(damage.rects(), &[Rect::new(0, 0, 5, 5)]);
+        for i in 1..=MAX_RECTS as i32 {
+            damage.add_buffer(Rect::new(i * 5, i * 5, 2, 2));
+        }
+        assert_eq!(damage.rects(), &[Rect::new(0, 0, 82, 82)]);
+    }
+}
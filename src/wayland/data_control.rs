@@ -0,0 +1,450 @@
+//! Handles the `zwlr_data_control_manager_v1` protocol.
+//!
+//! Allows privileged clients like clipboard managers to watch and control
+//! the selection of every seat without having a focused surface.
+use crate::wayland::event_queue::{EventDrain, EventQueue, EventSource};
+use crate::wayland::pipe::{FromRawFd, ReadPipe, WritePipe};
+use crate::wayland::seat::SeatManager;
+use std::sync::{Arc, Mutex};
+use wayland_client::{GlobalManager, NewProxy, Proxy};
+use wayland_protocols::wlr::unstable::data_control::v1::client::{
+    zwlr_data_control_device_v1::Event as DeviceEvent,
+    zwlr_data_control_offer_v1::Event as OfferEvent,
+    zwlr_data_control_source_v1::Event as SourceEvent,
+};
+pub use wayland_protocols::wlr::unstable::data_control::v1::client::{
+    zwlr_data_control_device_v1::RequestsTrait as DataControlDeviceRequests,
+    zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+    zwlr_data_control_manager_v1::RequestsTrait as DataControlManagerRequests,
+    zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    zwlr_data_control_offer_v1::RequestsTrait as DataControlOfferRequests,
+    zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+    zwlr_data_control_source_v1::RequestsTrait as DataControlSourceRequests,
+    zwlr_data_control_source_v1::ZwlrDataControlSourceV1,
+};
+
+/// Clipboard manager abstraction
+pub struct DataControl {
+    seat_manager: SeatManager,
+    manager: Proxy<ZwlrDataControlManagerV1>,
+    devices: Vec<(u32, Proxy<ZwlrDataControlDeviceV1>)>,
+    data_sources: Vec<(
+        u32,
+        Proxy<ZwlrDataControlSourceV1>,
+        EventDrain<DataControlSourceEvent>,
+    )>,
+    event_source: EventSource<DataControlEvent>,
+    event_drain: EventDrain<DataControlEvent>,
+}
+
+impl DataControl {
+    /// Creates a new `DataControl`
+    ///
+    /// Fails if the compositor did not advertise
+    /// `zwlr_data_control_manager_v1`.
+    pub fn new(
+        globals: &GlobalManager,
+        seat_manager: SeatManager,
+    ) -> Result<Self, ()> {
+        let manager = globals
+            .instantiate_auto(|manager| {
+                manager.implement(|event, _manager| match event {}, ())
+            })
+            .map_err(|_| ())?;
+        let (event_source, event_drain) = EventQueue::new();
+        let mut data_control = DataControl {
+            seat_manager,
+            manager,
+            devices: Vec::new(),
+            data_sources: Vec::new(),
+            event_source,
+            event_drain,
+        };
+        data_control.sync_devices();
+        Ok(data_control)
+    }
+
+    /// Creates a data control device for every new seat and destroys
+    /// the ones of removed seats
+    ///
+    /// Devices that were finished by the compositor are replaced.
+    fn sync_devices(&mut self) {
+        let seats = self.seat_manager.seats();
+        self.devices.retain(|(seat_id, device)| {
+            // finished devices were destroyed by their implementation
+            if !device.is_alive() {
+                return false;
+            }
+            let retain = seats.iter().any(|seat| seat.id() == *seat_id);
+            if !retain {
+                device.destroy();
+            }
+            retain
+        });
+        for seat in seats {
+            if self.devices.iter().any(|(id, _)| *id == seat.id()) {
+                continue;
+            }
+            let event_source = self.event_source.clone();
+            let seat_id = seat.id();
+            let device = self
+                .manager
+                .get_data_device(seat.proxy(), |device| {
+                    implement_data_control_device(device, seat_id, event_source)
+                })
+                .unwrap();
+            self.devices.push((seat_id, device));
+        }
+    }
+
+    fn get_device(
+        &self,
+        seat_id: u32,
+    ) -> Option<&Proxy<ZwlrDataControlDeviceV1>> {
+        self.devices
+            .iter()
+            .find(|(id, _)| *id == seat_id)
+            .map(|(_, device)| device)
+    }
+
+    fn get_offer(&self, seat_id: u32) -> Option<DataControlOffer> {
+        self.get_device(seat_id)?
+            .user_data::<Mutex<DataControlDeviceUserData>>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .selection
+            .clone()
+    }
+
+    /// The mime types offered by the current selection
+    ///
+    /// Returns an empty list if the selection is empty.
+    pub fn mime_types(&mut self, seat_id: u32) -> Vec<String> {
+        self.sync_devices();
+        self.get_offer(seat_id)
+            .map(|offer| offer.with_mime_types(|types| types.to_vec()))
+            .unwrap_or_default()
+    }
+
+    /// Set the selection content
+    ///
+    /// When a wayland client requests the contents a `DataControlEvent::Set`
+    /// will be emitted.
+    pub fn set(&mut self, seat_id: u32, mime_types: &[String]) {
+        self.sync_devices();
+        let device = match self.get_device(seat_id) {
+            Some(device) => device.clone(),
+            None => {
+                eprintln!("[SCTK] DataControl: unknown seat {}", seat_id);
+                return;
+            }
+        };
+        let (source, drain) = EventQueue::new();
+        let data_source = self
+            .manager
+            .create_data_source(|data_source| {
+                implement_data_control_source(data_source, source)
+            })
+            .unwrap();
+        for mime in mime_types {
+            data_source.offer(mime.to_owned());
+        }
+        device.set_selection(Some(&data_source));
+        self.data_sources.push((seat_id, data_source, drain));
+    }
+
+    /// Clear the selection
+    pub fn clear(&mut self, seat_id: u32) {
+        self.sync_devices();
+        match self.get_device(seat_id) {
+            Some(device) => device.set_selection(None),
+            None => eprintln!("[SCTK] DataControl: unknown seat {}", seat_id),
+        }
+    }
+
+    /// Get the selection contents in `mime_type`
+    ///
+    /// If the selection offers `mime_type` it will emit a
+    /// `DataControlEvent::Get` when the wayland client owning the
+    /// selection is ready to send the contents.
+    pub fn get(&mut self, seat_id: u32, mime_type: String) {
+        self.sync_devices();
+        if let Some(offer) = self.get_offer(seat_id) {
+            if !offer.with_mime_types(|types| types.contains(&mime_type)) {
+                return;
+            }
+            if let Some(pipe) = offer.receive(mime_type.clone()).ok() {
+                let event = DataControlEvent::Get {
+                    seat_id,
+                    pipe,
+                    mime_type,
+                };
+                self.event_source.push_event(event);
+            }
+        }
+    }
+
+    /// Polls the data control event queue
+    pub fn poll_events<F: FnMut(DataControlEvent)>(&mut self, mut cb: F) {
+        self.sync_devices();
+        self.data_sources.retain(|(seat_id, _, drain)| {
+            let mut retain = true;
+            drain.poll_events(|event| match event {
+                DataControlSourceEvent::Send { pipe, mime_type } => {
+                    let event = DataControlEvent::Set {
+                        seat_id: *seat_id,
+                        pipe,
+                        mime_type,
+                    };
+                    cb(event);
+                }
+                DataControlSourceEvent::Cancelled => {
+                    retain = false;
+                }
+            });
+            retain
+        });
+        self.event_drain.poll_events(|event| {
+            cb(event);
+        });
+    }
+}
+
+impl Drop for DataControl {
+    fn drop(&mut self) {
+        for (_, device) in &self.devices {
+            // finished devices were destroyed already
+            if device.is_alive() {
+                device.destroy();
+            }
+        }
+        for (_, data_source, _) in &self.data_sources {
+            // cancelled sources were destroyed already
+            if data_source.is_alive() {
+                data_source.destroy();
+            }
+        }
+        self.manager.destroy();
+    }
+}
+
+/// Events emitted by `DataControl`
+pub enum DataControlEvent {
+    /// The selection of a seat changed
+    Changed {
+        /// The seat id of the selection
+        seat_id: u32,
+        /// The mime types offered by the new selection
+        ///
+        /// Is empty if the selection was cleared.
+        mime_types: Vec<String>,
+    },
+    /// The selection contents are ready
+    Get {
+        /// The seat id of the selection
+        seat_id: u32,
+        /// The read pipe
+        pipe: ReadPipe,
+        /// The requested mime type
+        mime_type: String,
+    },
+    /// A client has requested the selection contents
+    Set {
+        /// The seat id of the selection
+        seat_id: u32,
+        /// The write pipe
+        pipe: WritePipe,
+        /// The negotiated mime type
+        mime_type: String,
+    },
+    /// The data control device of a seat is no longer valid
+    ///
+    /// Happens when the seat is removed. No more events will be emitted
+    /// for this seat.
+    Finished {
+        /// The seat id of the data control device
+        seat_id: u32,
+    },
+}
+
+/// Handles `zwlr_data_control_device_v1` events and forwards
+/// selection changes to an event queue.
+fn implement_data_control_device(
+    device: NewProxy<ZwlrDataControlDeviceV1>,
+    seat_id: u32,
+    event_queue: EventSource<DataControlEvent>,
+) -> Proxy<ZwlrDataControlDeviceV1> {
+    device.implement(
+        move |event, device| {
+            let mut user_data = device
+                .user_data::<Mutex<DataControlDeviceUserData>>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            match event {
+                DeviceEvent::DataOffer { id } => {
+                    user_data.offers.push(DataControlOffer::new(id));
+                }
+                DeviceEvent::Selection { id } => {
+                    let offer = user_data.take_offer(id);
+                    let mime_types = mime_types(&offer);
+                    user_data.selection = offer;
+                    event_queue.push_event(DataControlEvent::Changed {
+                        seat_id,
+                        mime_types,
+                    });
+                }
+                DeviceEvent::Finished => {
+                    user_data.selection = None;
+                    user_data.offers.clear();
+                    device.destroy();
+                    event_queue
+                        .push_event(DataControlEvent::Finished { seat_id });
+                }
+            }
+        },
+        Mutex::new(DataControlDeviceUserData::new()),
+    )
+}
+
+fn mime_types(offer: &Option<DataControlOffer>) -> Vec<String> {
+    offer
+        .as_ref()
+        .map(|offer| offer.with_mime_types(|types| types.to_vec()))
+        .unwrap_or_default()
+}
+
+/// `zwlr_data_control_device_v1` user data
+struct DataControlDeviceUserData {
+    /// The current selection
+    selection: Option<DataControlOffer>,
+    /// All offers anounced by the compositor
+    offers: Vec<DataControlOffer>,
+}
+
+impl DataControlDeviceUserData {
+    fn new() -> Self {
+        DataControlDeviceUserData {
+            selection: None,
+            offers: Vec::new(),
+        }
+    }
+
+    fn take_offer(
+        &mut self,
+        offer: Option<Proxy<ZwlrDataControlOfferV1>>,
+    ) -> Option<DataControlOffer> {
+        offer.and_then(|offer| {
+            self.offers
+                .iter()
+                .position(|o| o.offer.equals(&offer))
+                .map(|id| self.offers.swap_remove(id))
+        })
+    }
+}
+
+/// A data control offer for receiving the selection
+#[derive(Clone)]
+pub struct DataControlOffer {
+    offer: Proxy<ZwlrDataControlOfferV1>,
+    mime_types: Arc<Mutex<Vec<String>>>,
+}
+
+impl DataControlOffer {
+    fn new(offer: NewProxy<ZwlrDataControlOfferV1>) -> Self {
+        let mime_types = Arc::new(Mutex::new(Vec::new()));
+        // a weak handle, so that the last `DataControlOffer` clone sees
+        // itself as the last owner and destroys the offer
+        let mime_types2 = Arc::downgrade(&mime_types);
+        let offer = offer.implement(
+            move |event, _| match event {
+                OfferEvent::Offer { mime_type } => {
+                    if let Some(mime_types) = mime_types2.upgrade() {
+                        mime_types.lock().unwrap().push(mime_type);
+                    }
+                }
+            },
+            (),
+        );
+        DataControlOffer { offer, mime_types }
+    }
+
+    /// Access the list of mime types proposed by this offer
+    pub fn with_mime_types<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&[String]) -> T,
+    {
+        let mime_types = self.mime_types.lock().unwrap();
+        f(&mime_types)
+    }
+
+    /// Request to receive the data of a given mime type
+    ///
+    /// Fails if too many file descriptors were already open and a pipe
+    /// could not be created.
+    pub fn receive(&self, mime_type: String) -> Result<ReadPipe, ()> {
+        use nix::fcntl::OFlag;
+        use nix::unistd::{close, pipe2};
+        let (readfd, writefd) = pipe2(OFlag::O_CLOEXEC).map_err(|_| ())?;
+
+        self.offer.receive(mime_type, writefd);
+
+        if let Err(err) = close(writefd) {
+            eprintln!(
+                "[SCTK] Data control offer: failed to close write pipe: {}",
+                err
+            );
+        }
+
+        Ok(unsafe { FromRawFd::from_raw_fd(readfd) })
+    }
+}
+
+impl Drop for DataControlOffer {
+    fn drop(&mut self) {
+        // the last clone destroys the offer
+        if Arc::strong_count(&self.mime_types) == 1 {
+            self.offer.destroy();
+        }
+    }
+}
+
+/// Events of a data control source
+enum DataControlSourceEvent {
+    /// Write the offered data for selected mime type
+    Send {
+        /// Requested mime type
+        mime_type: String,
+        /// Pipe to write into
+        pipe: WritePipe,
+    },
+    /// The source was replaced by another one
+    Cancelled,
+}
+
+/// Handles `zwlr_data_control_source_v1` events and forwards them
+/// to an event queue.
+fn implement_data_control_source(
+    source: NewProxy<ZwlrDataControlSourceV1>,
+    event_queue: EventSource<DataControlSourceEvent>,
+) -> Proxy<ZwlrDataControlSourceV1> {
+    source.implement(
+        move |event, source| {
+            let event = match event {
+                SourceEvent::Send { mime_type, fd } => {
+                    DataControlSourceEvent::Send {
+                        mime_type,
+                        pipe: unsafe { FromRawFd::from_raw_fd(fd) },
+                    }
+                }
+                SourceEvent::Cancelled => {
+                    source.destroy();
+                    DataControlSourceEvent::Cancelled
+                }
+            };
+            event_queue.push_event(event);
+        },
+        (),
+    )
+}
@@ -13,9 +13,14 @@ use wayland_client::{NewProxy, Proxy};
 
 /// Handles `wl_data_device` events and forwards the ones
 /// that need user handling to an event queue.
+///
+/// Drag'n'drop events are queued to the surface the drag'n'drop entered,
+/// selection events to the surface that `selection_queue` follows, which
+/// should be the one with keyboard focus.
 pub fn implement_data_device(
     data_device: NewProxy<WlDataDevice>,
     mut event_queue: SeatEventSource<DataDeviceEvent>,
+    selection_queue: SeatEventSource<DataDeviceEvent>,
 ) -> Proxy<WlDataDevice> {
     data_device.implement(
         move |event, data_device| match event {
@@ -32,6 +37,9 @@ pub fn implement_data_device(
                     .lock()
                     .unwrap();
                 user_data.set_dnd(id);
+                if let Some(offer) = user_data.current_dnd.as_ref() {
+                    offer.set_serial(serial);
+                }
                 event_queue.enter_surface(&surface);
                 event_queue.queue_event(DataDeviceEvent::Enter {
                     x,
@@ -44,6 +52,12 @@ pub fn implement_data_device(
                 event_queue.queue_event(DataDeviceEvent::Motion { x, y, time });
             }
             Event::Leave => {
+                data_device
+                    .user_data::<Mutex<DataDeviceUserData>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .current_dnd = None;
                 event_queue.queue_event(DataDeviceEvent::Leave);
             }
             Event::Drop => {
@@ -55,7 +69,17 @@ pub fn implement_data_device(
                     .unwrap()
                     .lock()
                     .unwrap();
-                user_data.set_selection(id);
+                if user_data.set_selection(id) {
+                    let offer = user_data.selection.clone();
+                    let mime_types = offer
+                        .as_ref()
+                        .map(|offer| offer.with_mime_types(|t| t.to_vec()))
+                        .unwrap_or_default();
+                    selection_queue.queue_event(DataDeviceEvent::Selection {
+                        offer,
+                        mime_types,
+                    });
+                }
             }
             Event::DataOffer { id } => {
                 let mut user_data = data_device
@@ -76,7 +100,7 @@ pub struct DataDeviceUserData {
     selection: Option<DataOffer>,
     /// The current drag'n'drop
     current_dnd: Option<DataOffer>,
-    /// All data offers anounced by the compositor
+    /// Data offers anounced by the compositor that were not used yet
     offers: Vec<DataOffer>,
 }
 
@@ -90,33 +114,57 @@ impl DataDeviceUserData {
         }
     }
 
-    fn set_selection(&mut self, offer: Option<Proxy<WlDataOffer>>) {
-        if let Some(offer) = offer {
-            if let Some(id) =
-                self.offers.iter().position(|o| o.offer.equals(&offer))
-            {
-                self.selection = Some(self.offers.swap_remove(id));
-            } else {
-                panic!("Compositor set an unknown data_offer for selection.");
+    /// Takes the announced offer matching `offer`
+    ///
+    /// Every offer is announced right before it is used, so all other
+    /// unused offers are stale and get destroyed. Fails if the compositor
+    /// referenced an offer that was never announced.
+    fn take_offer(
+        &mut self,
+        offer: Option<Proxy<WlDataOffer>>,
+    ) -> Result<Option<DataOffer>, ()> {
+        let offer = match offer {
+            Some(offer) => offer,
+            None => return Ok(None),
+        };
+        let id = self
+            .offers
+            .iter()
+            .position(|o| o.offer.equals(&offer))
+            .ok_or(())?;
+        let offer = self.offers.swap_remove(id);
+        self.offers.clear();
+        Ok(Some(offer))
+    }
+
+    /// Replaces the selection, the previous one gets destroyed
+    ///
+    /// Returns `false` if the selection was ignored.
+    fn set_selection(&mut self, offer: Option<Proxy<WlDataOffer>>) -> bool {
+        match self.take_offer(offer) {
+            Ok(offer) => {
+                self.selection = offer;
+                true
+            }
+            Err(()) => {
+                eprintln!(
+                    "[SCTK] Data device: ignoring unknown data offer for selection"
+                );
+                false
             }
-        } else {
-            // drop the current offer if any
-            self.selection = None;
         }
     }
 
+    /// Replaces the drag'n'drop offer, the previous one gets destroyed
     fn set_dnd(&mut self, offer: Option<Proxy<WlDataOffer>>) {
-        if let Some(offer) = offer {
-            if let Some(id) =
-                self.offers.iter().position(|o| o.offer.equals(&offer))
-            {
-                self.current_dnd = Some(self.offers.swap_remove(id));
-            } else {
-                panic!("Compositor set an unknown data_offer for selection.");
+        match self.take_offer(offer) {
+            Ok(offer) => self.current_dnd = offer,
+            Err(()) => {
+                eprintln!(
+                    "[SCTK] Data device: ignoring unknown data offer for drag'n'drop"
+                );
+                self.current_dnd = None;
             }
-        } else {
-            // drop the current offer if any
-            self.current_dnd = None;
         }
     }
 }
@@ -151,6 +199,19 @@ pub enum DataDeviceEvent {
     Leave,
     /// The drag'n'drop was dropped on your surface
     Drop,
+    /// The selection changed
+    ///
+    /// Sent when the keyboard focus enters one of your surfaces and
+    /// whenever the selection changes while it has the focus. It is
+    /// queued to the surface with keyboard focus.
+    Selection {
+        /// The new selection, `None` if the selection was cleared
+        offer: Option<DataOffer>,
+        /// The mime types offered by the new selection
+        ///
+        /// Is empty if the selection was cleared.
+        mime_types: Vec<String>,
+    },
 }
 
 #[derive(Clone)]
@@ -31,10 +31,16 @@ impl DataOffer {
             current_action: DndAction::None,
             serial: 0,
         }));
-        let inner2 = inner.clone();
+        // a weak handle, so that the last `DataOffer` clone sees itself as
+        // the last owner and destroys the offer
+        let inner2 = Arc::downgrade(&inner);
         let offer = offer.implement(
             move |event, _| {
-                let mut inner = inner2.lock().unwrap();
+                let inner = match inner2.upgrade() {
+                    Some(inner) => inner,
+                    None => return,
+                };
+                let mut inner = inner.lock().unwrap();
                 match event {
                     Event::Offer { mime_type } => {
                         inner.mime_types.push(mime_type);
@@ -74,6 +80,18 @@ impl DataOffer {
         self.inner.lock().unwrap().current_action
     }
 
+    /// The `wl_data_offer` protocol version
+    ///
+    /// Actions and `finish` are only available starting with version 3.
+    pub fn version(&self) -> u32 {
+        self.offer.version()
+    }
+
+    /// Sets the serial of the drag'n'drop enter event the offer belongs to
+    pub(crate) fn set_serial(&self, serial: u32) {
+        self.inner.lock().unwrap().serial = serial;
+    }
+
     /// Accept a mime type for receiving data through this offer
     pub fn accept(&self, mime_type: Option<String>) {
         let serial = self.inner.lock().unwrap().serial;
@@ -139,6 +157,9 @@ impl std::fmt::Debug for DataOffer {
 
 impl Drop for DataOffer {
     fn drop(&mut self) {
-        self.offer.destroy();
+        // the last clone destroys the offer
+        if Arc::strong_count(&self.inner) == 1 {
+            self.offer.destroy();
+        }
     }
 }
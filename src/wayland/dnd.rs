@@ -0,0 +1,543 @@
+//! High level drag'n'drop handling
+//!
+//! `DragSession` drives the source side and `DropTarget` the destination
+//! side of a drag'n'drop on top of `DataDevice`, `DataSource` and
+//! `DataOffer`.
+use crate::wayland::clipboard::ClipboardData;
+use crate::wayland::data_device::DataDeviceEvent;
+use crate::wayland::data_device_manager::DndAction;
+use crate::wayland::data_offer::DataOffer;
+use crate::wayland::data_source::{
+    DataSourceEvent, DataSourceManager, DataSourceRequests, WlDataSource,
+};
+use crate::wayland::event_queue::{EventDrain, EventQueue, EventSource};
+use crate::wayland::pipe::RawFd;
+use crate::wayland::seat::SeatManager;
+use crate::wayland::surface::{SurfaceManager, SurfaceRequests, WlSurface};
+use crate::wayland::transfer::{TransferEngine, TransferEvent, TransferId};
+use std::io;
+pub use wayland_client::protocol::wl_buffer::WlBuffer;
+use wayland_client::Proxy;
+
+/// An icon that follows the pointer during a drag'n'drop
+pub struct DragIcon {
+    surface: Proxy<WlSurface>,
+}
+
+impl DragIcon {
+    /// Creates a `DragIcon` showing `buffer`
+    ///
+    /// `width` and `height` are the buffer dimensions in pixels, `scale`
+    /// is the buffer scale.
+    pub fn new(
+        surface_manager: &SurfaceManager,
+        buffer: &Proxy<WlBuffer>,
+        width: i32,
+        height: i32,
+        scale: i32,
+    ) -> Self {
+        let surface = surface_manager.create_surface();
+        surface.attach(Some(buffer), 0, 0);
+        surface.set_buffer_scale(scale);
+        if surface.version() >= 4 {
+            surface.damage_buffer(0, 0, width, height);
+        } else {
+            surface.damage(0, 0, width / scale, height / scale);
+        }
+        surface.commit();
+        DragIcon { surface }
+    }
+
+    /// Returns the `wl_surface`
+    pub fn surface(&self) -> &Proxy<WlSurface> {
+        &self.surface
+    }
+}
+
+impl Drop for DragIcon {
+    fn drop(&mut self) {
+        self.surface.destroy();
+    }
+}
+
+/// What a `DragSession` offers
+pub struct DragSource {
+    data: ClipboardData,
+    actions: DndAction,
+    icon: Option<DragIcon>,
+}
+
+impl DragSource {
+    /// Creates a `DragSource` offering all representations of `data`
+    ///
+    /// Only copying is offered by default.
+    pub fn new(data: ClipboardData) -> Self {
+        DragSource {
+            data,
+            actions: DndAction::Copy,
+            icon: None,
+        }
+    }
+
+    /// Sets the actions the target may choose from
+    pub fn with_actions(mut self, actions: DndAction) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    /// Sets the icon that follows the pointer
+    pub fn with_icon(mut self, icon: DragIcon) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+}
+
+/// The source side of a drag'n'drop
+pub struct DragSession {
+    data_source: Proxy<WlDataSource>,
+    event_drain: EventDrain<DataSourceEvent>,
+    data: ClipboardData,
+    icon: Option<DragIcon>,
+    action: DndAction,
+    finished: bool,
+    transfer_engine: TransferEngine,
+    transfers: Vec<(TransferId, String)>,
+}
+
+impl DragSession {
+    /// Starts a drag'n'drop of `source`
+    ///
+    /// `serial` needs to be the serial of an implicit grab on `origin`,
+    /// for example of the pointer button press that started the drag.
+    ///
+    /// Fails if the seat has no data device.
+    pub fn start(
+        seat_manager: &SeatManager,
+        data_source_manager: &DataSourceManager,
+        seat_id: u32,
+        origin: &Proxy<WlSurface>,
+        source: DragSource,
+        serial: u32,
+    ) -> Result<Self, ()> {
+        let DragSource {
+            data,
+            actions,
+            icon,
+        } = source;
+        let data_device = seat_manager.get_data_device(seat_id).ok_or(())?;
+        let (data_source, event_drain) = data_source_manager
+            .create_data_source(&data.mime_types())
+            .split();
+        data_device.start_drag(
+            origin,
+            Some(&data_source),
+            actions,
+            icon.as_ref().map(|icon| icon.surface()),
+            serial,
+        );
+        Ok(DragSession {
+            data_source,
+            event_drain,
+            data,
+            icon,
+            action: DndAction::None,
+            finished: false,
+            transfer_engine: TransferEngine::new(),
+            transfers: Vec::new(),
+        })
+    }
+
+    /// The action chosen by the target
+    pub fn action(&self) -> DndAction {
+        self.action
+    }
+
+    /// Returns `true` once the drag'n'drop finished or was cancelled
+    ///
+    /// The session can be dropped afterwards.
+    pub fn is_finished(&self) -> bool {
+        self.finished && self.transfer_engine.is_empty()
+    }
+
+    /// The file descriptors of pending transfers
+    pub fn fds(&self) -> Vec<RawFd> {
+        self.transfer_engine.fds()
+    }
+
+    /// Polls the drag'n'drop events
+    ///
+    /// Also sends the data to the target and advances pending transfers
+    /// without blocking.
+    pub fn poll_events<F: FnMut(DragEvent)>(&mut self, mut cb: F) {
+        let mut finished = None;
+        {
+            let data = &self.data;
+            let transfer_engine = &mut self.transfer_engine;
+            let transfers = &mut self.transfers;
+            let action = &mut self.action;
+            self.event_drain.poll_events(|event| match event {
+                DataSourceEvent::Send { pipe, mime_type } => {
+                    let content = match data.content(&mime_type) {
+                        Some(content) => content.data(&mime_type),
+                        None => return,
+                    };
+                    match transfer_engine.write(pipe, content) {
+                        Ok(id) => transfers.push((id, mime_type)),
+                        Err(error) => cb(DragEvent::Error { mime_type, error }),
+                    }
+                }
+                DataSourceEvent::Target { mime_type } => {
+                    cb(DragEvent::Target { mime_type });
+                }
+                DataSourceEvent::Action { action: new_action } => {
+                    *action = new_action;
+                    cb(DragEvent::Action { action: new_action });
+                }
+                DataSourceEvent::Dropped => {
+                    cb(DragEvent::Dropped);
+                }
+                DataSourceEvent::Finished => {
+                    finished = Some(DragEvent::Finished { action: *action });
+                }
+                DataSourceEvent::Cancelled => {
+                    finished = Some(DragEvent::Cancelled);
+                }
+            });
+        }
+        let transfers = &mut self.transfers;
+        self.transfer_engine.dispatch(0, |event| {
+            let id = match event {
+                TransferEvent::Read { id, .. }
+                | TransferEvent::Written { id }
+                | TransferEvent::Error { id, .. } => id,
+            };
+            let position = transfers.iter().position(|(id2, _)| *id2 == id);
+            let (_, mime_type) = transfers.remove(position.unwrap());
+            if let TransferEvent::Error { error, .. } = event {
+                cb(DragEvent::Error { mime_type, error });
+            }
+        });
+        if let Some(event) = finished {
+            // the data source was destroyed by its implementation
+            self.finished = true;
+            self.icon = None;
+            cb(event);
+        }
+    }
+}
+
+impl Drop for DragSession {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.data_source.destroy();
+        }
+    }
+}
+
+/// Events of a `DragSession`
+#[derive(Debug)]
+pub enum DragEvent {
+    /// The target accepted a mime type
+    ///
+    /// Is `None` if the current target does not accept any of the
+    /// offered mime types. Use it to give feedback, for example by
+    /// changing the cursor.
+    Target {
+        /// The accepted mime type
+        mime_type: Option<String>,
+    },
+    /// The target and compositor selected an action
+    Action {
+        /// The selected action
+        action: DndAction,
+    },
+    /// The user dropped, the target may still cancel
+    Dropped,
+    /// The target finished the drag'n'drop
+    ///
+    /// If `action` is `DndAction::Move` the source data should be deleted
+    /// now.
+    Finished {
+        /// The final action
+        action: DndAction,
+    },
+    /// The drag'n'drop was cancelled
+    Cancelled,
+    /// Sending the data to the target failed
+    Error {
+        /// The requested mime type
+        mime_type: String,
+        /// The error
+        error: io::Error,
+    },
+}
+
+/// The destination side of a drag'n'drop
+///
+/// Feed it the `DataDeviceEvent`s of the surface that accepts drops.
+pub struct DropTarget {
+    mime_types: Vec<String>,
+    actions: DndAction,
+    preferred_action: DndAction,
+    offer: Option<DataOffer>,
+    mime_type: Option<String>,
+    accepted: bool,
+    position: (f64, f64),
+    transfer_engine: TransferEngine,
+    transfers: Vec<(TransferId, DataOffer, String)>,
+    event_source: EventSource<DropEvent>,
+    event_drain: EventDrain<DropEvent>,
+}
+
+impl DropTarget {
+    /// Creates a new `DropTarget`
+    ///
+    /// `mime_types` are the accepted mime types in order of preference,
+    /// for example `mime::text_mime_types()` for text or
+    /// `mime::TEXT_URI_LIST` for files. `actions` are the supported actions
+    /// and `preferred_action` the action used when the user doesn't choose
+    /// one.
+    pub fn new(
+        mime_types: Vec<String>,
+        actions: DndAction,
+        preferred_action: DndAction,
+    ) -> Self {
+        let (event_source, event_drain) = EventQueue::new();
+        DropTarget {
+            mime_types,
+            actions,
+            preferred_action,
+            offer: None,
+            mime_type: None,
+            accepted: false,
+            position: (0.0, 0.0),
+            transfer_engine: TransferEngine::new(),
+            transfers: Vec::new(),
+            event_source,
+            event_drain,
+        }
+    }
+
+    /// Handles a `DataDeviceEvent`
+    ///
+    /// `accepts` is called with the surface coordinates of the drag'n'drop
+    /// on every enter and motion and returns whether a drop is possible
+    /// at that position.
+    pub fn handle_event<F: FnMut(f64, f64) -> bool>(
+        &mut self,
+        event: &DataDeviceEvent,
+        mut accepts: F,
+    ) {
+        match event {
+            DataDeviceEvent::Enter { offer, x, y, .. } => {
+                self.offer = offer.clone();
+                self.mime_type = self.offer.as_ref().and_then(|offer| {
+                    let mime_types = &self.mime_types;
+                    offer.with_mime_types(|offer_types| {
+                        mime_types
+                            .iter()
+                            .find(|mime_type| offer_types.contains(mime_type))
+                            .cloned()
+                    })
+                });
+                self.position = (*x, *y);
+                self.accepted = false;
+                self.update_accept(accepts(*x, *y));
+                let mime_types = self
+                    .offer
+                    .as_ref()
+                    .map(|offer| offer.with_mime_types(|types| types.to_vec()))
+                    .unwrap_or_default();
+                self.event_source.push_event(DropEvent::Enter {
+                    x: *x,
+                    y: *y,
+                    mime_types,
+                });
+            }
+            DataDeviceEvent::Motion { x, y, .. } => {
+                self.position = (*x, *y);
+                self.update_accept(accepts(*x, *y));
+                self.event_source.push_event(DropEvent::Motion {
+                    x: *x,
+                    y: *y,
+                    accepted: self.accepted,
+                });
+            }
+            DataDeviceEvent::Leave => {
+                self.reset();
+                self.event_source.push_event(DropEvent::Leave);
+            }
+            DataDeviceEvent::Drop => {
+                let offer = self.offer.take();
+                let mime_type = self.mime_type.take();
+                match (offer, mime_type) {
+                    (Some(ref offer), Some(ref mime_type)) if self.accepted => {
+                        self.receive(offer, mime_type);
+                    }
+                    _ => {}
+                }
+                self.accepted = false;
+            }
+            DataDeviceEvent::Selection { .. } => {}
+        }
+    }
+
+    /// Accepts or rejects the offer at the current position
+    fn update_accept(&mut self, accepts: bool) {
+        let offer = match self.offer.as_ref() {
+            Some(offer) => offer,
+            None => return,
+        };
+        let accepted = accepts && self.mime_type.is_some();
+        if accepted {
+            offer.accept(self.mime_type.clone());
+            if offer.version() >= 3 {
+                offer.set_actions(self.actions, self.preferred_action);
+            }
+        } else {
+            offer.accept(None);
+            if offer.version() >= 3 {
+                offer.set_actions(DndAction::None, DndAction::None);
+            }
+        }
+        self.accepted = accepted;
+    }
+
+    fn receive(&mut self, offer: &DataOffer, mime_type: &str) {
+        let result = offer
+            .receive(mime_type.to_owned())
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::Other, "failed to create pipe")
+            })
+            .and_then(|pipe| self.transfer_engine.read(pipe));
+        match result {
+            Ok(id) => {
+                self.transfers
+                    .push((id, offer.clone(), mime_type.to_owned()));
+            }
+            Err(error) => {
+                self.event_source.push_event(DropEvent::Error {
+                    mime_type: mime_type.to_owned(),
+                    error,
+                });
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.offer = None;
+        self.mime_type = None;
+        self.accepted = false;
+    }
+
+    /// The current position of the drag'n'drop on the surface
+    pub fn position(&self) -> (f64, f64) {
+        self.position
+    }
+
+    /// The file descriptors of pending transfers
+    pub fn fds(&self) -> Vec<RawFd> {
+        self.transfer_engine.fds()
+    }
+
+    /// Finishes a drop whose action was `DndAction::Ask`
+    ///
+    /// `action` is the action the user chose, it has to be one of the
+    /// available actions of the offer.
+    pub fn finish_ask(&self, offer: &DataOffer, action: DndAction) {
+        if offer.version() >= 3 {
+            offer.set_actions(action, action);
+            offer.finish();
+        }
+    }
+
+    /// Polls the drop events
+    ///
+    /// Also advances the transfer of dropped data without blocking.
+    pub fn poll_events<F: FnMut(DropEvent)>(&mut self, mut cb: F) {
+        let transfers = &mut self.transfers;
+        let event_source = &self.event_source;
+        self.transfer_engine.dispatch(0, |event| {
+            let id = match event {
+                TransferEvent::Read { id, .. }
+                | TransferEvent::Written { id }
+                | TransferEvent::Error { id, .. } => id,
+            };
+            let position = transfers.iter().position(|(id2, _, _)| *id2 == id);
+            let (_, offer, mime_type) = transfers.remove(position.unwrap());
+            match event {
+                TransferEvent::Read { data, .. } => {
+                    let action = offer.get_current_action();
+                    // `Ask` is finished by the application with `finish_ask`
+                    if offer.version() >= 3 && action != DndAction::Ask {
+                        offer.finish();
+                    }
+                    event_source.push_event(DropEvent::Drop {
+                        mime_type,
+                        data,
+                        action,
+                        offer,
+                    });
+                }
+                TransferEvent::Error { error, .. } => {
+                    event_source
+                        .push_event(DropEvent::Error { mime_type, error });
+                }
+                TransferEvent::Written { .. } => {}
+            }
+        });
+        self.event_drain.poll_events(|event| {
+            cb(event);
+        });
+    }
+}
+
+/// Events of a `DropTarget`
+#[derive(Debug)]
+pub enum DropEvent {
+    /// A drag'n'drop entered the surface
+    Enter {
+        /// horizontal location on the surface
+        x: f64,
+        /// vertical location on the surface
+        y: f64,
+        /// The mime types offered by the source
+        ///
+        /// Is empty for a drag'n'drop internal to your application.
+        mime_types: Vec<String>,
+    },
+    /// The drag'n'drop moved on the surface
+    Motion {
+        /// new horizontal location
+        x: f64,
+        /// new vertical location
+        y: f64,
+        /// Whether a drop is accepted at this location
+        accepted: bool,
+    },
+    /// The drag'n'drop left the surface
+    Leave,
+    /// The data was dropped and received
+    Drop {
+        /// The negotiated mime type
+        mime_type: String,
+        /// The received data
+        ///
+        /// Decode it with `mime::Payload::decode`.
+        data: Vec<u8>,
+        /// The final action
+        ///
+        /// If it is `DndAction::Ask` let the user choose an action and call
+        /// `DropTarget::finish_ask` with `offer`.
+        action: DndAction,
+        /// The data offer
+        offer: DataOffer,
+    },
+    /// Receiving the dropped data failed
+    Error {
+        /// The negotiated mime type
+        mime_type: String,
+        /// The error
+        error: io::Error,
+    },
+}
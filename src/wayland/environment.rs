@@ -6,7 +6,9 @@ use crate::wayland::cursor::CursorManager;
 use crate::wayland::data_device_manager::initialize_data_device_manager;
 use crate::wayland::data_source::DataSourceManager;
 use crate::wayland::event_queue::EventQueue;
-use crate::wayland::output::{OutputManager, OutputManagerEvent};
+use crate::wayland::output::{
+    initialize_xdg_output_manager, OutputManager, OutputManagerEvent,
+};
 use crate::wayland::seat::{SeatManager, SeatManagerEvent};
 use crate::wayland::shm::{initialize_shm, WlShm};
 use crate::wayland::surface::SurfaceManager;
@@ -102,8 +104,10 @@ impl Environment {
         let subcompositor = initialize_subcompositor(&globals);
         let shm = initialize_shm(&globals);
         let data_device_manager = initialize_data_device_manager(&globals);
+        let xdg_output_manager = initialize_xdg_output_manager(&globals);
 
         let output_manager = OutputManager::new(
+            xdg_output_manager,
             output_manager_drain,
             surface_manager_source.clone(),
             cursor_manager_source.clone(),
@@ -0,0 +1,312 @@
+//! Handles the `zwlr_gamma_control_manager_v1` protocol.
+//!
+//! Allows privileged clients like night lights to set the gamma ramps of
+//! the outputs.
+use crate::wayland::event_queue::{EventDrain, EventQueue, EventSource};
+use crate::wayland::mem_pool::create_shm_fd;
+use crate::wayland::output::{OutputManager, WlOutput};
+use byteorder::{NativeEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::{Arc, Mutex};
+use wayland_client::{GlobalManager, NewProxy, Proxy};
+use wayland_protocols::wlr::unstable::gamma_control::v1::client::zwlr_gamma_control_v1::Event as GammaEvent;
+pub use wayland_protocols::wlr::unstable::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::RequestsTrait as GammaControlManagerRequests,
+    zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1,
+    zwlr_gamma_control_v1::RequestsTrait as GammaControlRequests,
+    zwlr_gamma_control_v1::ZwlrGammaControlV1,
+};
+
+/// The state of a gamma control
+struct GammaState {
+    /// The number of elements of each ramp
+    size: Option<u32>,
+    /// The control is inert
+    failed: bool,
+}
+
+/// Gamma control abstraction
+///
+/// The gamma ramps are reset when the `GammaControl` is dropped.
+pub struct GammaControl {
+    manager: Proxy<ZwlrGammaControlManagerV1>,
+    output_manager: OutputManager,
+    controls: Vec<(
+        Proxy<WlOutput>,
+        Proxy<ZwlrGammaControlV1>,
+        Arc<Mutex<GammaState>>,
+    )>,
+    event_source: EventSource<GammaControlEvent>,
+    event_drain: EventDrain<GammaControlEvent>,
+}
+
+impl GammaControl {
+    /// Creates a new `GammaControl`
+    ///
+    /// Fails if the compositor did not advertise
+    /// `zwlr_gamma_control_manager_v1`.
+    pub fn new(
+        globals: &GlobalManager,
+        output_manager: OutputManager,
+    ) -> Result<Self, ()> {
+        let manager = globals
+            .instantiate_auto(|manager| {
+                manager.implement(|event, _manager| match event {}, ())
+            })
+            .map_err(|_| ())?;
+        let (event_source, event_drain) = EventQueue::new();
+        let mut gamma_control = GammaControl {
+            manager,
+            output_manager,
+            controls: Vec::new(),
+            event_source,
+            event_drain,
+        };
+        gamma_control.sync_controls();
+        Ok(gamma_control)
+    }
+
+    /// Creates a gamma control for every new output and destroys the
+    /// ones of removed outputs
+    fn sync_controls(&mut self) {
+        let outputs = self.output_manager.outputs();
+        self.controls.retain(|(output, control, _)| {
+            let retain = outputs.iter().any(|output2| output2.equals(output));
+            if !retain {
+                control.destroy();
+            }
+            retain
+        });
+        for output in outputs {
+            if self
+                .controls
+                .iter()
+                .any(|(output2, _, _)| output2.equals(&output))
+            {
+                continue;
+            }
+            let state = Arc::new(Mutex::new(GammaState {
+                size: None,
+                failed: false,
+            }));
+            let state2 = state.clone();
+            let event_source = self.event_source.clone();
+            let output2 = output.clone();
+            let control = self
+                .manager
+                .get_gamma_control(&output, |control| {
+                    implement_gamma_control(
+                        control,
+                        output2,
+                        state2,
+                        event_source,
+                    )
+                })
+                .unwrap();
+            self.controls.push((output, control, state));
+        }
+    }
+
+    /// The number of elements of each gamma ramp of `output`
+    ///
+    /// Is `None` until the compositor reported it or if the gamma of
+    /// `output` can't be controlled.
+    pub fn gamma_size(&self, output: &Proxy<WlOutput>) -> Option<u32> {
+        self.controls
+            .iter()
+            .find(|(output2, _, _)| output2.equals(output))
+            .and_then(|(_, _, state)| {
+                let state = state.lock().unwrap();
+                if state.failed {
+                    None
+                } else {
+                    state.size
+                }
+            })
+    }
+
+    /// Sets the gamma ramps of `output`
+    ///
+    /// `ramps` contains the red, green and blue ramps one after the other,
+    /// each with `gamma_size` elements.
+    pub fn set_gamma(
+        &mut self,
+        output: &Proxy<WlOutput>,
+        ramps: &[u16],
+    ) -> io::Result<()> {
+        self.sync_controls();
+        let size = self.gamma_size(output).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "the gamma of the output can't be controlled",
+            )
+        })?;
+        if ramps.len() != 3 * size as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the ramps don't match the gamma size",
+            ));
+        }
+        let mut file = unsafe { File::from_raw_fd(create_shm_fd()?) };
+        {
+            let mut writer = BufWriter::new(&mut file);
+            for value in ramps {
+                writer.write_u16::<NativeEndian>(*value)?;
+            }
+            writer.flush()?;
+        }
+        file.seek(SeekFrom::Start(0))?;
+        let (_, control, _) = self
+            .controls
+            .iter()
+            .find(|(output2, _, _)| output2.equals(output))
+            .unwrap();
+        // the fd is duplicated when the request is sent
+        control.set_gamma(file.as_raw_fd());
+        Ok(())
+    }
+
+    /// Sets the gamma ramps of `output` to a color temperature
+    ///
+    /// See `temperature_ramps`.
+    pub fn set_temperature(
+        &mut self,
+        output: &Proxy<WlOutput>,
+        kelvin: u32,
+        brightness: f64,
+    ) -> io::Result<()> {
+        self.sync_controls();
+        let size = self.gamma_size(output).unwrap_or(0);
+        let ramps = temperature_ramps(size as usize, kelvin, brightness);
+        self.set_gamma(output, &ramps)
+    }
+
+    /// Polls the gamma control events
+    pub fn poll_events<F: FnMut(GammaControlEvent)>(&mut self, mut cb: F) {
+        self.sync_controls();
+        self.event_drain.poll_events(|event| {
+            cb(event);
+        });
+    }
+}
+
+impl Drop for GammaControl {
+    fn drop(&mut self) {
+        for (_, control, _) in &self.controls {
+            control.destroy();
+        }
+        self.manager.destroy();
+    }
+}
+
+/// Events emitted by `GammaControl`
+#[derive(Clone)]
+pub enum GammaControlEvent {
+    /// The size of the gamma ramps of an output is known
+    GammaSize {
+        /// The output
+        output: Proxy<WlOutput>,
+        /// The number of elements of each ramp
+        size: u32,
+    },
+    /// The gamma of the output can't be controlled
+    ///
+    /// The output was removed, another client controls its gamma or the
+    /// ramps were invalid.
+    Failed {
+        /// The output
+        output: Proxy<WlOutput>,
+    },
+}
+
+/// Handles `zwlr_gamma_control_v1` events
+fn implement_gamma_control(
+    control: NewProxy<ZwlrGammaControlV1>,
+    output: Proxy<WlOutput>,
+    state: Arc<Mutex<GammaState>>,
+    event_source: EventSource<GammaControlEvent>,
+) -> Proxy<ZwlrGammaControlV1> {
+    control.implement(
+        move |event, _control| {
+            let mut state = state.lock().unwrap();
+            let output = output.clone();
+            let event = match event {
+                GammaEvent::GammaSize { size } => {
+                    state.size = Some(size);
+                    GammaControlEvent::GammaSize { output, size }
+                }
+                GammaEvent::Failed => {
+                    state.failed = true;
+                    GammaControlEvent::Failed { output }
+                }
+            };
+            event_source.push_event(event);
+        },
+        (),
+    )
+}
+
+/// Computes gamma ramps with `size` elements for a color temperature
+///
+/// `kelvin` is clamped to 1000K to 40000K, 6500K leaves the colors
+/// unchanged. `brightness` scales the ramps and is clamped to 0.0 to 1.0.
+/// Returns the red, green and blue ramps one after the other.
+pub fn temperature_ramps(
+    size: usize,
+    kelvin: u32,
+    brightness: f64,
+) -> Vec<u16> {
+    let (red, green, blue) = temperature_to_rgb(kelvin);
+    let brightness = brightness.max(0.0).min(1.0);
+    let mut ramps = Vec::with_capacity(3 * size);
+    for factor in &[red, green, blue] {
+        for i in 0..size {
+            let value = if size > 1 {
+                i as f64 / (size - 1) as f64
+            } else {
+                1.0
+            };
+            let value =
+                value * factor * brightness * f64::from(u16::max_value());
+            ramps.push(value.round() as u16);
+        }
+    }
+    ramps
+}
+
+/// Approximates the color of a black body with the temperature `kelvin`
+///
+/// The channels are normalized so that 6500K is white.
+fn temperature_to_rgb(kelvin: u32) -> (f64, f64, f64) {
+    let rgb = |kelvin: u32| {
+        let t = f64::from(kelvin.max(1000).min(40000)) / 100.0;
+        let red = if t <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (t - 60.0).powf(-0.1332047592)
+        };
+        let green = if t <= 66.0 {
+            99.4708025861 * t.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (t - 60.0).powf(-0.0755148492)
+        };
+        let blue = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (t - 10.0).ln() - 305.0447927307
+        };
+        let clamp = |c: f64| (c / 255.0).max(0.0).min(1.0);
+        (clamp(red), clamp(green), clamp(blue))
+    };
+    let (red, green, blue) = rgb(kelvin);
+    let (white_red, white_green, white_blue) = rgb(6500);
+    (
+        (red / white_red).min(1.0),
+        (green / white_green).min(1.0),
+        (blue / white_blue).min(1.0),
+    )
+}
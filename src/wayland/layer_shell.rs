@@ -1,6 +1,8 @@
 //! Handles the `zwlr_layer_shell_v1` protocol.
 use crate::wayland::event_queue::{EventDrain, EventQueue};
-use crate::wayland::output::{OutputUserData, WlOutput};
+use crate::wayland::output::{OutputUserData, Transform, WlOutput};
+use crate::wayland::presentation::PresentationFeedback;
+use crate::wayland::region::Region;
 use crate::wayland::seat::SeatEvent;
 use crate::wayland::surface::{
     SurfaceEvent, SurfaceManager, SurfaceRequests, SurfaceUserData, WlSurface,
@@ -94,6 +96,7 @@ impl LayerShell {
         layer_surface.set_size(size.0, size.1);
         surface.commit();
         LayerShellSurface {
+            surface_manager: self.surface_manager.clone(),
             surface,
             layer_surface,
             layout,
@@ -105,6 +108,7 @@ impl LayerShell {
 
 /// A layer shell surface
 pub struct LayerShellSurface {
+    surface_manager: SurfaceManager,
     surface: Proxy<WlSurface>,
     layer_surface: Proxy<ZwlrLayerSurfaceV1>,
     layout: Layout,
@@ -149,15 +153,63 @@ impl LayerShellSurface {
                 SurfaceEvent::Scale { scale_factor } => {
                     cb(LayerSurfaceEvent::Scale { scale_factor }, self);
                 }
+                SurfaceEvent::PreferredTransform { transform } => {
+                    cb(
+                        LayerSurfaceEvent::PreferredTransform { transform },
+                        self,
+                    );
+                }
+                SurfaceEvent::Frame { time } => {
+                    cb(LayerSurfaceEvent::Frame { time }, self);
+                }
+                SurfaceEvent::Presentation {
+                    feedback_id,
+                    feedback,
+                } => {
+                    let event = LayerSurfaceEvent::Presentation {
+                        feedback_id,
+                        feedback,
+                    };
+                    cb(event, self);
+                }
                 SurfaceEvent::Seat { seat_id, event } => {
                     cb(LayerSurfaceEvent::Seat { seat_id, event }, self);
                 }
+                SurfaceEvent::SubsurfaceSeat {
+                    surface_id,
+                    seat_id,
+                    event,
+                } => {
+                    let event = LayerSurfaceEvent::SubsurfaceSeat {
+                        surface_id,
+                        seat_id,
+                        event,
+                    };
+                    cb(event, self);
+                }
             });
         }
         self.event_drain.poll_events(|event| {
             cb(event, self);
         });
     }
+
+    /// Sets the region that accepts input
+    ///
+    /// `None` accepts input on the whole surface, an empty region makes
+    /// the surface click-through. Takes effect on the next commit.
+    pub fn set_input_region(&self, region: Option<&Region>) {
+        self.surface_manager.set_input_region(&self.surface, region);
+    }
+
+    /// Sets the region that is opaque
+    ///
+    /// `None` marks the whole surface as translucent. Takes effect on the
+    /// next commit.
+    pub fn set_opaque_region(&self, region: Option<&Region>) {
+        self.surface_manager
+            .set_opaque_region(&self.surface, region);
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -168,6 +220,24 @@ pub enum LayerSurfaceEvent {
         /// New scale factor
         scale_factor: u32,
     },
+    /// The transform the compositor prefers buffers to be rendered with
+    /// has changed
+    PreferredTransform {
+        /// The new preferred transform
+        transform: Transform,
+    },
+    /// It is a good time to draw the next frame
+    Frame {
+        /// A timestamp in milliseconds with undefined base
+        time: u32,
+    },
+    /// Presentation feedback was received
+    Presentation {
+        /// The id returned by `Presentation::feedback`
+        feedback_id: u32,
+        /// How the commit was presented
+        feedback: PresentationFeedback,
+    },
     /// A seat event was received
     Seat {
         /// Seat that sent the event
@@ -175,6 +245,15 @@ pub enum LayerSurfaceEvent {
         /// The sent event
         event: SeatEvent,
     },
+    /// A seat event was received by a subsurface
+    SubsurfaceSeat {
+        /// The id of the subsurface's `wl_surface`
+        surface_id: u32,
+        /// Seat that sent the event
+        seat_id: u32,
+        /// The sent event
+        event: SeatEvent,
+    },
     /// The state of your window has been changed
     Configure {
         /// Optional new size for your shell surface
@@ -226,12 +305,7 @@ impl Layout {
             .unwrap()
             .lock()
             .unwrap();
-        let dimensions = output_user_data
-            .modes
-            .iter()
-            .find(|mode| mode.is_current)
-            .unwrap()
-            .dimensions;
+        let dimensions = output_user_data.logical_size().unwrap_or((0, 0));
         match *self {
             Layout::BarBottom { height } => (dimensions.0 as _, height),
         }
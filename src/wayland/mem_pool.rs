@@ -242,7 +242,7 @@ impl io::Seek for MemPool {
     }
 }
 
-fn create_shm_fd() -> io::Result<RawFd> {
+pub(crate) fn create_shm_fd() -> io::Result<RawFd> {
     // Only try memfd on linux
     #[cfg(target_os = "linux")]
     loop {
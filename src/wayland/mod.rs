@@ -2,23 +2,33 @@
 pub mod clipboard;
 pub mod compositor;
 pub mod cursor;
+pub mod damage;
+pub mod data_control;
 pub mod data_device;
 pub mod data_device_manager;
 pub mod data_offer;
 pub mod data_source;
+pub mod dnd;
 pub mod environment;
 pub mod event_queue;
+pub mod gamma_control;
 pub mod keyboard;
 pub mod layer_shell;
 pub mod mem_pool;
 pub mod output;
 pub mod pipe;
 pub mod pointer;
+pub mod presentation;
+pub mod primary_selection;
+pub mod region;
 pub mod seat;
 pub mod shm;
+pub mod subsurface;
 pub mod surface;
 pub mod toplevel_manager;
 pub mod touch;
+pub mod transfer;
+pub mod xdg_popup;
 pub mod xdg_shell;
 pub mod xkbcommon;
 
@@ -1,6 +1,6 @@
 //! Output device handling
 use crate::wayland::cursor::CursorManagerEvent;
-use crate::wayland::event_queue::{EventDrain, EventSource};
+use crate::wayland::event_queue::{EventDrain, EventQueue, EventSource};
 use crate::wayland::surface::SurfaceManagerEvent;
 use std::sync::{Arc, Mutex};
 pub use wayland_client::protocol::wl_output::RequestsTrait as OutputRequests;
@@ -9,29 +9,68 @@ use wayland_client::protocol::wl_output::{Event, Mode as WlMode};
 pub use wayland_client::protocol::wl_output::{Subpixel, Transform};
 use wayland_client::protocol::wl_registry::RequestsTrait as RegistryRequests;
 use wayland_client::protocol::wl_registry::WlRegistry;
-use wayland_client::Proxy;
+use wayland_client::{GlobalManager, NewProxy, Proxy};
+use wayland_protocols::unstable::xdg_output::v1::client::zxdg_output_v1::Event as XdgOutputEvent;
+pub use wayland_protocols::unstable::xdg_output::v1::client::{
+    zxdg_output_manager_v1::RequestsTrait as XdgOutputManagerRequests,
+    zxdg_output_manager_v1::ZxdgOutputManagerV1,
+    zxdg_output_v1::RequestsTrait as XdgOutputRequests,
+    zxdg_output_v1::ZxdgOutputV1,
+};
+
+/// Initializes the `zxdg_output_manager_v1`
+///
+/// Returns `None` if the compositor did not advertise
+/// `zxdg_output_manager_v1`.
+pub fn initialize_xdg_output_manager(
+    globals: &GlobalManager,
+) -> Option<Proxy<ZxdgOutputManagerV1>> {
+    globals
+        .instantiate_auto(|xdg_output_manager| {
+            xdg_output_manager
+                .implement(|event, _xdg_output_manager| match event {}, ())
+        })
+        .ok()
+}
+
+/// The highest supported `wl_output` version
+///
+/// Is the version of the `wl_output` bindings, names and descriptions are
+/// taken from `zxdg_output_v1`.
+pub const OUTPUT_VERSION: u32 = 3;
 
 /// Handles `wl_output` devices
 #[derive(Clone)]
 pub struct OutputManager {
-    outputs: Arc<Mutex<Vec<Proxy<WlOutput>>>>,
+    outputs: Arc<Mutex<Vec<Output>>>,
+    xdg_output_manager: Option<Proxy<ZxdgOutputManagerV1>>,
     event_drain: EventDrain<OutputManagerEvent>,
-    surface_manager_source: EventSource<SurfaceManagerEvent>,
-    cursor_manager_source: EventSource<CursorManagerEvent>,
+    notifier: OutputNotifier,
+    output_event_drain: EventDrain<OutputEvent>,
 }
 
 impl OutputManager {
     /// Creates a new `OutputManager`
+    ///
+    /// The logical geometry, name and description of the outputs are
+    /// only known if `xdg_output_manager` is available.
     pub fn new(
+        xdg_output_manager: Option<Proxy<ZxdgOutputManagerV1>>,
         event_drain: EventDrain<OutputManagerEvent>,
         surface_manager_source: EventSource<SurfaceManagerEvent>,
         cursor_manager_source: EventSource<CursorManagerEvent>,
     ) -> Self {
+        let (output_event_source, output_event_drain) = EventQueue::new();
         OutputManager {
             outputs: Arc::new(Mutex::new(Vec::new())),
+            xdg_output_manager,
             event_drain,
-            surface_manager_source,
-            cursor_manager_source,
+            notifier: OutputNotifier {
+                event_source: output_event_source,
+                surface_manager_source,
+                cursor_manager_source,
+            },
+            output_event_drain,
         }
     }
 
@@ -41,19 +80,22 @@ impl OutputManager {
         version: u32,
         registry: &Proxy<WlRegistry>,
     ) {
-        let surface_manager_source = self.surface_manager_source.clone();
-        let cursor_manager_source = self.cursor_manager_source.clone();
+        let pending = Arc::new(Mutex::new(PendingOutput::new()));
+        let pending2 = pending.clone();
+        let notifier = self.notifier.clone();
+        let version = std::cmp::min(version, OUTPUT_VERSION);
         let output = registry
             .bind(version, output_id, |output| {
                 output.implement(
                     move |event, output| {
-                        let mut user_data = output
-                            .user_data::<Mutex<OutputUserData>>()
-                            .unwrap()
-                            .lock()
-                            .unwrap();
+                        let mut pending = pending2.lock().unwrap();
                         match event {
-                            Event::Done => {}
+                            Event::Done => {
+                                pending.done = true;
+                                pending.wl_output_done();
+                                pending.apply(&output, &notifier);
+                                return;
+                            }
                             Event::Geometry {
                                 x,
                                 y,
@@ -64,13 +106,14 @@ impl OutputManager {
                                 make,
                                 transform,
                             } => {
-                                user_data.location = (x, y);
-                                user_data.physical_size =
+                                let state = &mut pending.state;
+                                state.location = (x, y);
+                                state.physical_size =
                                     (physical_width, physical_height);
-                                user_data.subpixel = subpixel;
-                                user_data.transform = transform;
-                                user_data.model = model;
-                                user_data.make = make;
+                                state.subpixel = subpixel;
+                                state.transform = transform;
+                                state.model = model;
+                                state.make = make;
                             }
                             Event::Mode {
                                 width,
@@ -85,8 +128,14 @@ impl OutputManager {
                                 let is_current =
                                     flags.contains(WlMode::Current);
 
+                                let modes = &mut pending.state.modes;
+                                if is_current {
+                                    for mode in modes.iter_mut() {
+                                        mode.is_current = false;
+                                    }
+                                }
                                 let existing_mode =
-                                    user_data.modes.iter_mut().find(|mode| {
+                                    modes.iter_mut().find(|mode| {
                                         mode.dimensions == dimensions
                                             && mode.refresh_rate == refresh_rate
                                     });
@@ -102,62 +151,143 @@ impl OutputManager {
                                             is_preferred,
                                             is_current,
                                         };
-                                        user_data.modes.push(mode);
+                                        modes.push(mode);
                                     }
                                 }
                             }
                             Event::Scale { factor } => {
-                                let factor = factor as u32;
-                                user_data.scale_factor = factor;
-                                let event = SurfaceManagerEvent::OutputScale {
-                                    output: output.clone(),
-                                    factor,
-                                };
-                                surface_manager_source.push_event(event);
-                                let event = CursorManagerEvent::OutputScale {
-                                    output: output.clone(),
-                                    factor,
-                                };
-                                cursor_manager_source.push_event(event);
+                                pending.state.scale_factor = factor as u32;
                             }
                         }
+                        // `done` was added in version 2
+                        if output.version() < 2 {
+                            pending.done = true;
+                            pending.apply(&output, &notifier);
+                        }
                     },
                     Mutex::new(OutputUserData::new()),
                 )
             })
             .unwrap();
-        self.outputs.lock().unwrap().push(output);
+        if let Some(ref xdg_output_manager) = self.xdg_output_manager {
+            let notifier = self.notifier.clone();
+            let pending2 = pending.clone();
+            let xdg_output = xdg_output_manager
+                .get_xdg_output(&output, |xdg_output| {
+                    implement_xdg_output(
+                        xdg_output,
+                        output.clone(),
+                        pending2,
+                        notifier,
+                    )
+                })
+                .unwrap();
+            pending.lock().unwrap().xdg_output = Some(xdg_output);
+        }
+        self.outputs.lock().unwrap().push(Output {
+            id: output_id,
+            output,
+            pending,
+        });
     }
 
     fn remove_output(&self, output_id: u32) {
-        let output = self.get_output(output_id).unwrap();
+        let removed = {
+            let mut outputs = self.outputs.lock().unwrap();
+            match outputs.iter().position(|output| output.id == output_id) {
+                Some(position) => outputs.remove(position),
+                None => {
+                    eprintln!(
+                        "[SCTK] OutputManager: removed unknown output {}",
+                        output_id
+                    );
+                    return;
+                }
+            }
+        };
+        let (output, pending) = (removed.output, removed.pending);
         let event = SurfaceManagerEvent::OutputLeave {
             output: output.clone(),
         };
-        self.surface_manager_source.push_event(event);
-        let event = CursorManagerEvent::OutputLeave { output };
-        self.cursor_manager_source.push_event(event);
-        self.outputs.lock().unwrap().retain(|output| {
-            if output.id() == output_id && output.version() >= 3 {
-                output.release();
-            }
-            output.id() != output_id
-        });
+        self.notifier.surface_manager_source.push_event(event);
+        let event = CursorManagerEvent::OutputLeave {
+            output: output.clone(),
+        };
+        self.notifier.cursor_manager_source.push_event(event);
+        let pending = pending.lock().unwrap();
+        if let Some(ref xdg_output) = pending.xdg_output {
+            xdg_output.destroy();
+        }
+        if pending.announced {
+            let info = output
+                .user_data::<Mutex<OutputUserData>>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .clone();
+            let event = OutputEvent::Removed {
+                output: output.clone(),
+                info,
+            };
+            self.notifier.event_source.push_event(event);
+        }
+        if output.version() >= 3 {
+            output.release();
+        }
     }
 
     /// A list of all current outputs
-    pub fn outputs(&self) -> &Arc<Mutex<Vec<Proxy<WlOutput>>>> {
-        &self.outputs
+    pub fn outputs(&self) -> Vec<Proxy<WlOutput>> {
+        self.outputs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|output| output.output.clone())
+            .collect()
     }
 
-    /// The `wl_output` with `output_id`
+    /// The `wl_output` of the global with `output_id`
+    ///
+    /// The global id changes when an output is unplugged and plugged in
+    /// again, use `find_output` to find it by name.
     pub fn get_output(&self, output_id: u32) -> Option<Proxy<WlOutput>> {
         self.outputs
             .lock()
             .unwrap()
             .iter()
-            .find(|output| output.id() == output_id)
-            .map(|output| output.clone())
+            .find(|output| output.id == output_id)
+            .map(|output| output.output.clone())
+    }
+
+    /// The `wl_output` with the connector `name`, for example `DP-1`
+    ///
+    /// Matches the identifier of `OutputUserData::identifier`.
+    pub fn find_output(&self, name: &str) -> Option<Proxy<WlOutput>> {
+        self.outputs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|output| {
+                output
+                    .output
+                    .user_data::<Mutex<OutputUserData>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .identifier()
+                    == Some(name)
+            })
+            .map(|output| output.output.clone())
+    }
+
+    /// Polls the output events
+    ///
+    /// An output is announced once all its initial state was received,
+    /// later changes are reported once per reconfiguration.
+    pub fn poll_events<F: FnMut(OutputEvent)>(&self, mut cb: F) {
+        self.output_event_drain.poll_events(|event| {
+            cb(event);
+        });
     }
 
     /// Processes it's event queues and dispatches events to when necessary
@@ -178,6 +308,152 @@ impl OutputManager {
     }
 }
 
+/// A bound `wl_output` global
+struct Output {
+    /// The global id
+    id: u32,
+    output: Proxy<WlOutput>,
+    pending: Arc<Mutex<PendingOutput>>,
+}
+
+/// Forwards applied output changes to the application and to the
+/// `SurfaceManager` and `CursorManager`
+#[derive(Clone)]
+struct OutputNotifier {
+    event_source: EventSource<OutputEvent>,
+    surface_manager_source: EventSource<SurfaceManagerEvent>,
+    cursor_manager_source: EventSource<CursorManagerEvent>,
+}
+
+/// The output state received since the last `done`
+struct PendingOutput {
+    state: OutputUserData,
+    xdg_output: Option<Proxy<ZxdgOutputV1>>,
+    /// A `wl_output.done` was received
+    done: bool,
+    /// A `zxdg_output_v1` event was received
+    xdg_received: bool,
+    /// The initial `zxdg_output_v1` state is complete
+    xdg_done: bool,
+    /// `OutputEvent::Added` was emitted
+    announced: bool,
+}
+
+impl PendingOutput {
+    fn new() -> Self {
+        PendingOutput {
+            state: OutputUserData::new(),
+            xdg_output: None,
+            done: false,
+            xdg_received: false,
+            xdg_done: false,
+            announced: false,
+        }
+    }
+
+    /// Since version 3 `zxdg_output_v1` state is applied by
+    /// `wl_output.done`
+    fn wl_output_done(&mut self) {
+        let xdg_version = self.xdg_output.as_ref().map(|xdg| xdg.version());
+        if xdg_version.map(|version| version >= 3).unwrap_or(false)
+            && self.xdg_received
+        {
+            self.xdg_done = true;
+        }
+    }
+
+    /// Applies the pending state to the user data of `output` and emits
+    /// what changed
+    ///
+    /// The output is only announced once the initial `wl_output` and
+    /// `zxdg_output_v1` state were received.
+    fn apply(&mut self, output: &Proxy<WlOutput>, notifier: &OutputNotifier) {
+        if !self.done || (self.xdg_output.is_some() && !self.xdg_done) {
+            return;
+        }
+        let previous = {
+            let mut user_data = output
+                .user_data::<Mutex<OutputUserData>>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            std::mem::replace(&mut *user_data, self.state.clone())
+        };
+        let changes = OutputChanges::diff(&previous, &self.state);
+        if !self.announced || changes.scale_factor {
+            let factor = self.state.scale_factor;
+            let event = SurfaceManagerEvent::OutputScale {
+                output: output.clone(),
+                factor,
+            };
+            notifier.surface_manager_source.push_event(event);
+            let event = CursorManagerEvent::OutputScale {
+                output: output.clone(),
+                factor,
+            };
+            notifier.cursor_manager_source.push_event(event);
+        }
+        if !self.announced || changes.transform {
+            let event = SurfaceManagerEvent::OutputTransform {
+                output: output.clone(),
+                transform: self.state.transform,
+            };
+            notifier.surface_manager_source.push_event(event);
+        }
+        let event = if !self.announced {
+            self.announced = true;
+            OutputEvent::Added {
+                output: output.clone(),
+                info: self.state.clone(),
+            }
+        } else if changes.is_empty() {
+            return;
+        } else {
+            OutputEvent::Changed {
+                output: output.clone(),
+                info: self.state.clone(),
+                previous,
+                changes,
+            }
+        };
+        notifier.event_source.push_event(event);
+    }
+}
+
+/// Handles `zxdg_output_v1` events of `output`
+fn implement_xdg_output(
+    xdg_output: NewProxy<ZxdgOutputV1>,
+    output: Proxy<WlOutput>,
+    pending: Arc<Mutex<PendingOutput>>,
+    notifier: OutputNotifier,
+) -> Proxy<ZxdgOutputV1> {
+    xdg_output.implement(
+        move |event, _xdg_output| {
+            let mut pending = pending.lock().unwrap();
+            pending.xdg_received = true;
+            match event {
+                XdgOutputEvent::LogicalPosition { x, y } => {
+                    pending.state.logical_position = (x, y);
+                }
+                XdgOutputEvent::LogicalSize { width, height } => {
+                    pending.state.logical_size = Some((width, height));
+                }
+                XdgOutputEvent::Name { name } => {
+                    pending.state.name = Some(name);
+                }
+                XdgOutputEvent::Description { description } => {
+                    pending.state.description = Some(description);
+                }
+                XdgOutputEvent::Done => {
+                    pending.xdg_done = true;
+                    pending.apply(&output, &notifier);
+                }
+            }
+        },
+        (),
+    )
+}
+
 #[derive(Clone, Debug)]
 /// Compiled information about an output
 pub struct OutputUserData {
@@ -211,6 +487,20 @@ pub struct OutputUserData {
     pub scale_factor: u32,
     /// Possible modes for an output
     pub modes: Vec<Mode>,
+    /// Location of the top-left corner of this output in the global
+    /// compositor space, as advertised by `zxdg_output_v1`
+    pub logical_position: (i32, i32),
+    /// Size of this output in the global compositor space, scaling and
+    /// transform applied
+    ///
+    /// Is `None` without `zxdg_output_v1`.
+    pub logical_size: Option<(i32, i32)>,
+    /// The name of this output, for example `DP-1`
+    ///
+    /// Is `None` without `zxdg_output_v1`.
+    pub name: Option<String>,
+    /// A human readable description of this output
+    pub description: Option<String>,
 }
 
 impl OutputUserData {
@@ -225,12 +515,48 @@ impl OutputUserData {
             transform: Transform::Normal,
             scale_factor: 1,
             modes: Vec::new(),
+            logical_position: (0, 0),
+            logical_size: None,
+            name: None,
+            description: None,
         }
     }
+
+    /// A stable identifier of this output
+    ///
+    /// It is the connector name, like `DP-1`, announced through
+    /// `zxdg_output_v1`. Unlike the global id it stays the same when the
+    /// output is unplugged and plugged in again.
+    ///
+    /// Is `None` without `zxdg_output_v1`, the make and model are not
+    /// unique enough to tell identical monitors apart.
+    pub fn identifier(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| name.as_str())
+    }
+
+    /// The size of this output in the global compositor space
+    ///
+    /// Without `zxdg_output_v1` it is computed from the current mode,
+    /// the scale factor and the transform.
+    pub fn logical_size(&self) -> Option<(u32, u32)> {
+        if let Some((width, height)) = self.logical_size {
+            return Some((width as u32, height as u32));
+        }
+        let (width, height) =
+            self.modes.iter().find(|mode| mode.is_current)?.dimensions;
+        let (width, height) = match self.transform {
+            Transform::_90
+            | Transform::_270
+            | Transform::Flipped90
+            | Transform::Flipped270 => (height, width),
+            _ => (width, height),
+        };
+        Some((width / self.scale_factor, height / self.scale_factor))
+    }
 }
 
 /// A possible mode for an output
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Mode {
     /// Number of pixels of this mode in format `(width, height)`
     ///
@@ -244,6 +570,80 @@ pub struct Mode {
     pub is_preferred: bool,
 }
 
+/// The parts of an output that changed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OutputChanges {
+    /// The location, physical size, subpixel layout, make or model
+    pub geometry: bool,
+    /// The transform
+    pub transform: bool,
+    /// The scale factor
+    pub scale_factor: bool,
+    /// The modes or the current mode
+    pub modes: bool,
+    /// The logical position or size
+    pub logical_geometry: bool,
+    /// The name
+    pub name: bool,
+    /// The description
+    pub description: bool,
+}
+
+impl OutputChanges {
+    fn diff(previous: &OutputUserData, current: &OutputUserData) -> Self {
+        OutputChanges {
+            geometry: previous.location != current.location
+                || previous.physical_size != current.physical_size
+                || previous.subpixel != current.subpixel
+                || previous.make != current.make
+                || previous.model != current.model,
+            transform: previous.transform != current.transform,
+            scale_factor: previous.scale_factor != current.scale_factor,
+            modes: previous.modes != current.modes,
+            logical_geometry: previous.logical_position
+                != current.logical_position
+                || previous.logical_size != current.logical_size,
+            name: previous.name != current.name,
+            description: previous.description != current.description,
+        }
+    }
+
+    /// Returns `true` if nothing changed
+    pub fn is_empty(&self) -> bool {
+        *self == OutputChanges::default()
+    }
+}
+
+/// Events emitted by `OutputManager`
+#[derive(Clone)]
+pub enum OutputEvent {
+    /// An output was added
+    Added {
+        /// The output
+        output: Proxy<WlOutput>,
+        /// The state of the output
+        info: OutputUserData,
+    },
+    /// An output was reconfigured
+    Changed {
+        /// The output
+        output: Proxy<WlOutput>,
+        /// The new state of the output
+        info: OutputUserData,
+        /// The state before the reconfiguration
+        previous: OutputUserData,
+        /// What changed
+        changes: OutputChanges,
+    },
+    /// An output was removed
+    Removed {
+        /// The removed output
+        output: Proxy<WlOutput>,
+        /// The last state of the output
+        info: OutputUserData,
+    },
+}
+
 /// The events that a `OutputManager` needs to know about
 #[derive(Clone)]
 pub enum OutputManagerEvent {
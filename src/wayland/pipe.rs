@@ -1,13 +1,33 @@
 //! Pipe abstraction for dnd and clipboard handling
 use std::fs::File;
-use std::io::{Read, Result, Write};
+use std::io::{Error, Read, Result, Write};
 pub use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 
-/// A file descriptor that can only be written to
+/// Sets `O_NONBLOCK` on a file descriptor
+fn set_nonblocking(fd: RawFd) -> Result<()> {
+    use nix::fcntl::{fcntl, FcntlArg, OFlag};
+    let to_io_error = |err| match err {
+        nix::Error::Sys(errno) => Error::from(errno),
+        err => Error::new(std::io::ErrorKind::Other, err),
+    };
+    let flags = fcntl(fd, FcntlArg::F_GETFL).map_err(to_io_error)?;
+    let flags = OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK;
+    fcntl(fd, FcntlArg::F_SETFL(flags)).map_err(to_io_error)?;
+    Ok(())
+}
+
+/// A file descriptor that can only be read from
 pub struct ReadPipe {
     file: File,
 }
 
+impl ReadPipe {
+    /// Makes reads return `WouldBlock` instead of waiting for data
+    pub fn set_nonblocking(&self) -> Result<()> {
+        set_nonblocking(self.as_raw_fd())
+    }
+}
+
 impl Read for ReadPipe {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         self.file.read(buf)
@@ -39,6 +59,13 @@ pub struct WritePipe {
     file: File,
 }
 
+impl WritePipe {
+    /// Makes writes return `WouldBlock` instead of waiting for the reader
+    pub fn set_nonblocking(&self) -> Result<()> {
+        set_nonblocking(self.as_raw_fd())
+    }
+}
+
 impl Write for WritePipe {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         self.file.write(buf)
@@ -0,0 +1,161 @@
+//! Handles the `wp_presentation` protocol.
+//!
+//! Reports when and how the content of a surface was shown on an output.
+use crate::wayland::surface::{SurfaceEvent, SurfaceUserData, WlSurface};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use wayland_client::{GlobalManager, NewProxy, Proxy};
+use wayland_protocols::presentation_time::client::{
+    wp_presentation::Event as PresentationEvent,
+    wp_presentation_feedback::Event as FeedbackEvent,
+};
+pub use wayland_protocols::presentation_time::client::{
+    wp_presentation::RequestsTrait as PresentationRequests,
+    wp_presentation::WpPresentation,
+    wp_presentation_feedback::Kind as PresentationKind,
+    wp_presentation_feedback::WpPresentationFeedback,
+};
+
+/// Presentation time abstraction
+pub struct Presentation {
+    presentation: Proxy<WpPresentation>,
+    clock_id: Arc<Mutex<Option<u32>>>,
+}
+
+impl Presentation {
+    /// Creates a new `Presentation`
+    ///
+    /// Fails if the compositor did not advertise `wp_presentation`.
+    pub fn new(globals: &GlobalManager) -> Result<Self, ()> {
+        let clock_id = Arc::new(Mutex::new(None));
+        let clock_id2 = clock_id.clone();
+        let presentation = globals
+            .instantiate_auto(|presentation| {
+                presentation.implement(
+                    move |event, _presentation| match event {
+                        PresentationEvent::ClockId { clk_id } => {
+                            *clock_id2.lock().unwrap() = Some(clk_id);
+                        }
+                    },
+                    (),
+                )
+            })
+            .map_err(|_| ())?;
+        Ok(Presentation {
+            presentation,
+            clock_id,
+        })
+    }
+
+    /// The clock of the presentation timestamps
+    ///
+    /// Pass it to `clock_gettime` to compare the timestamps with the
+    /// current time. Is `None` until the compositor announced it.
+    pub fn clock_id(&self) -> Option<u32> {
+        *self.clock_id.lock().unwrap()
+    }
+
+    /// Requests presentation feedback for the next commit of `surface`
+    ///
+    /// Call it before committing. The result is emitted as a
+    /// `SurfaceEvent::Presentation` with the returned feedback id.
+    pub fn feedback(&self, surface: &Proxy<WlSurface>) -> u32 {
+        let surface2 = surface.clone();
+        let feedback = self
+            .presentation
+            .feedback(surface, |feedback| {
+                implement_feedback(feedback, surface2)
+            })
+            .unwrap();
+        feedback.id()
+    }
+}
+
+impl Drop for Presentation {
+    fn drop(&mut self) {
+        self.presentation.destroy();
+    }
+}
+
+/// How the content of a commit was presented
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PresentationFlags(u32);
+
+impl PresentationFlags {
+    /// Returns `true` if `kind` is set
+    pub fn contains(self, kind: PresentationKind) -> bool {
+        self.0 & kind.to_raw() != 0
+    }
+}
+
+/// The presentation of a commit
+#[derive(Clone, Copy, Debug)]
+pub enum PresentationFeedback {
+    /// The content was shown
+    Presented {
+        /// When the content was turned into light
+        ///
+        /// Measured with the clock of `Presentation::clock_id`.
+        time: Duration,
+        /// The refresh interval of the output
+        ///
+        /// Is `None` if the output has no constant refresh rate.
+        refresh: Option<Duration>,
+        /// The value of the vertical retrace counter of the output
+        sequence: u64,
+        /// How the content was presented
+        flags: PresentationFlags,
+    },
+    /// The content was never shown, for example because it was
+    /// superseded by a later commit or the surface was hidden
+    Discarded,
+}
+
+/// Handles `wp_presentation_feedback` events of `surface`
+fn implement_feedback(
+    feedback: NewProxy<WpPresentationFeedback>,
+    surface: Proxy<WlSurface>,
+) -> Proxy<WpPresentationFeedback> {
+    feedback.implement(
+        move |event, feedback| {
+            let result = match event {
+                FeedbackEvent::SyncOutput { .. } => return,
+                FeedbackEvent::Presented {
+                    tv_sec_hi,
+                    tv_sec_lo,
+                    tv_nsec,
+                    refresh,
+                    seq_hi,
+                    seq_lo,
+                    flags,
+                } => {
+                    let secs =
+                        (u64::from(tv_sec_hi) << 32) | u64::from(tv_sec_lo);
+                    let refresh = if refresh == 0 {
+                        None
+                    } else {
+                        Some(Duration::new(0, refresh))
+                    };
+                    PresentationFeedback::Presented {
+                        time: Duration::new(secs, tv_nsec),
+                        refresh,
+                        sequence: (u64::from(seq_hi) << 32) | u64::from(seq_lo),
+                        flags: PresentationFlags(flags),
+                    }
+                }
+                FeedbackEvent::Discarded => PresentationFeedback::Discarded,
+            };
+            surface
+                .user_data::<Mutex<SurfaceUserData>>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .event_source
+                .push_event(SurfaceEvent::Presentation {
+                    feedback_id: feedback.id(),
+                    feedback: result,
+                });
+        },
+        (),
+    )
+}
@@ -0,0 +1,505 @@
+//! Handles the `zwp_primary_selection_device_manager_v1` protocol.
+use crate::wayland::clipboard::ClipboardData;
+use crate::wayland::event_queue::{EventDrain, EventQueue, EventSource};
+use crate::wayland::pipe::{FromRawFd, RawFd, ReadPipe, WritePipe};
+use crate::wayland::seat::SeatManager;
+use crate::wayland::transfer::{TransferEngine, TransferEvent, TransferId};
+use std::io;
+use std::sync::{Arc, Mutex};
+use wayland_client::{GlobalManager, NewProxy, Proxy};
+pub use wayland_protocols::unstable::primary_selection::v1::client::{
+    zwp_primary_selection_device_manager_v1::RequestsTrait as PrimarySelectionDeviceManagerRequests,
+    zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1,
+    zwp_primary_selection_device_v1::RequestsTrait as PrimarySelectionDeviceRequests,
+    zwp_primary_selection_device_v1::ZwpPrimarySelectionDeviceV1,
+    zwp_primary_selection_offer_v1::RequestsTrait as PrimarySelectionOfferRequests,
+    zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1,
+    zwp_primary_selection_source_v1::RequestsTrait as PrimarySelectionSourceRequests,
+    zwp_primary_selection_source_v1::ZwpPrimarySelectionSourceV1,
+};
+use wayland_protocols::unstable::primary_selection::v1::client::{
+    zwp_primary_selection_device_v1::Event as DeviceEvent,
+    zwp_primary_selection_offer_v1::Event as OfferEvent,
+    zwp_primary_selection_source_v1::Event as SourceEvent,
+};
+
+/// Primary selection abstraction
+///
+/// The primary selection is the text that was last selected, it is
+/// traditionally pasted with a middle click.
+pub struct PrimarySelection {
+    seat_manager: SeatManager,
+    manager: Proxy<ZwpPrimarySelectionDeviceManagerV1>,
+    devices: Vec<(u32, Proxy<ZwpPrimarySelectionDeviceV1>)>,
+    data_sources: Vec<(
+        u32,
+        Proxy<ZwpPrimarySelectionSourceV1>,
+        ClipboardData,
+        EventDrain<PrimarySelectionSourceEvent>,
+    )>,
+    transfer_engine: TransferEngine,
+    transfers: Vec<(TransferId, u32, String)>,
+    event_source: EventSource<PrimarySelectionEvent>,
+    event_drain: EventDrain<PrimarySelectionEvent>,
+}
+
+impl PrimarySelection {
+    /// Creates a new `PrimarySelection`
+    ///
+    /// Fails if the compositor did not advertise
+    /// `zwp_primary_selection_device_manager_v1`.
+    pub fn new(
+        globals: &GlobalManager,
+        seat_manager: SeatManager,
+    ) -> Result<Self, ()> {
+        let manager = globals
+            .instantiate_auto(|manager| {
+                manager.implement(|event, _manager| match event {}, ())
+            })
+            .map_err(|_| ())?;
+        let (event_source, event_drain) = EventQueue::new();
+        let mut primary_selection = PrimarySelection {
+            seat_manager,
+            manager,
+            devices: Vec::new(),
+            data_sources: Vec::new(),
+            transfer_engine: TransferEngine::new(),
+            transfers: Vec::new(),
+            event_source,
+            event_drain,
+        };
+        primary_selection.sync_devices();
+        Ok(primary_selection)
+    }
+
+    /// Creates a primary selection device for every new seat and
+    /// destroys the ones of removed seats
+    fn sync_devices(&mut self) {
+        let seats = self.seat_manager.seats();
+        self.devices.retain(|(seat_id, device)| {
+            let retain = seats.iter().any(|seat| seat.id() == *seat_id);
+            if !retain {
+                device.destroy();
+            }
+            retain
+        });
+        for seat in seats {
+            if self.devices.iter().any(|(id, _)| *id == seat.id()) {
+                continue;
+            }
+            let device = self
+                .manager
+                .get_device(seat.proxy(), |device| {
+                    implement_primary_selection_device(device)
+                })
+                .unwrap();
+            self.devices.push((seat.id(), device));
+        }
+    }
+
+    fn get_device(
+        &self,
+        seat_id: u32,
+    ) -> Option<&Proxy<ZwpPrimarySelectionDeviceV1>> {
+        self.devices
+            .iter()
+            .find(|(id, _)| *id == seat_id)
+            .map(|(_, device)| device)
+    }
+
+    /// Set the primary selection content
+    ///
+    /// Notifies the compositor that the primary selection has been updated
+    /// and offers all representations of `data`. When a wayland client
+    /// requests the primary selection contents the requested representation
+    /// is sent to it.
+    pub fn set(&mut self, seat_id: u32, serial: u32, data: ClipboardData) {
+        self.sync_devices();
+        let device = match self.get_device(seat_id) {
+            Some(device) => device.clone(),
+            None => {
+                eprintln!("[SCTK] PrimarySelection: unknown seat {}", seat_id);
+                return;
+            }
+        };
+        let (source, drain) = EventQueue::new();
+        let data_source = self
+            .manager
+            .create_source(|data_source| {
+                implement_primary_selection_source(data_source, source)
+            })
+            .unwrap();
+        for mime in data.mime_types() {
+            data_source.offer(mime);
+        }
+        device.set_selection(Some(&data_source), serial);
+        self.data_sources.push((seat_id, data_source, data, drain));
+    }
+
+    /// Get the primary selection contents
+    ///
+    /// Picks the first mime type of `preferences` that the primary selection
+    /// offers, use `mime::text_mime_types` to paste text.
+    ///
+    /// If the primary selection isn't empty it will emit a
+    /// PrimarySelectionEvent::Get once the contents were received from the
+    /// wayland client setting the primary selection.
+    ///
+    /// If the primary selection is owned by this `PrimarySelection` a
+    /// PrimarySelectionEvent::GetLocal with the data is emitted instead.
+    pub fn get(&mut self, seat_id: u32, preferences: &[String]) {
+        self.sync_devices();
+        if let Some((_, _, data, _)) = self
+            .data_sources
+            .iter()
+            .rev()
+            .find(|(id, _, _, _)| *id == seat_id)
+        {
+            if let Some(mime_type) = data.negotiate(preferences) {
+                let data = data.content(&mime_type).unwrap().data(&mime_type);
+                let event = PrimarySelectionEvent::GetLocal {
+                    seat_id,
+                    mime_type,
+                    data,
+                };
+                self.event_source.push_event(event);
+            }
+            return;
+        }
+        let device = match self.get_device(seat_id) {
+            Some(device) => device,
+            None => {
+                eprintln!("[SCTK] PrimarySelection: unknown seat {}", seat_id);
+                return;
+            }
+        };
+        let offer = device
+            .user_data::<Mutex<PrimarySelectionDeviceUserData>>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .selection
+            .clone();
+        if let Some(offer) = offer {
+            if let Some(mime_type) = offer.with_mime_types(|offer_types| {
+                preferences
+                    .iter()
+                    .find(|mime_type| offer_types.contains(mime_type))
+                    .cloned()
+            }) {
+                if let Some(pipe) = offer.receive(mime_type.clone()).ok() {
+                    self.read(seat_id, mime_type, pipe);
+                }
+            }
+        }
+    }
+
+    fn read(&mut self, seat_id: u32, mime_type: String, pipe: ReadPipe) {
+        match self.transfer_engine.read(pipe) {
+            Ok(id) => self.transfers.push((id, seat_id, mime_type)),
+            Err(error) => {
+                let event = PrimarySelectionEvent::Error {
+                    seat_id,
+                    mime_type,
+                    error,
+                };
+                self.event_source.push_event(event);
+            }
+        }
+    }
+
+    /// The file descriptors of pending transfers
+    ///
+    /// Add these to your event loop to call `poll_events` as soon as a
+    /// transfer can make progress.
+    pub fn fds(&self) -> Vec<RawFd> {
+        self.transfer_engine.fds()
+    }
+
+    /// Polls the primary selection event queue
+    ///
+    /// Also sends the primary selection contents to clients that requested
+    /// them and advances pending transfers without blocking.
+    pub fn poll_events<F: FnMut(PrimarySelectionEvent)>(&mut self, mut cb: F) {
+        self.sync_devices();
+        let transfer_engine = &mut self.transfer_engine;
+        let transfers = &mut self.transfers;
+        let event_source = &self.event_source;
+        self.data_sources.retain(|(seat_id, _, data, drain)| {
+            let mut retain = true;
+            drain.poll_events(|event| match event {
+                PrimarySelectionSourceEvent::Send { pipe, mime_type } => {
+                    let data = match data.content(&mime_type) {
+                        Some(content) => content.data(&mime_type),
+                        None => return,
+                    };
+                    match transfer_engine.write(pipe, data) {
+                        Ok(id) => transfers.push((id, *seat_id, mime_type)),
+                        Err(error) => {
+                            let event = PrimarySelectionEvent::Error {
+                                seat_id: *seat_id,
+                                mime_type,
+                                error,
+                            };
+                            event_source.push_event(event);
+                        }
+                    }
+                }
+                PrimarySelectionSourceEvent::Cancelled => {
+                    retain = false;
+                }
+            });
+            retain
+        });
+        let transfers = &mut self.transfers;
+        self.transfer_engine.dispatch(0, |event| {
+            let id = match event {
+                TransferEvent::Read { id, .. }
+                | TransferEvent::Written { id }
+                | TransferEvent::Error { id, .. } => id,
+            };
+            let position = transfers.iter().position(|(id2, _, _)| *id2 == id);
+            let (_, seat_id, mime_type) = transfers.remove(position.unwrap());
+            let event = match event {
+                TransferEvent::Read { data, .. } => {
+                    PrimarySelectionEvent::Get {
+                        seat_id,
+                        mime_type,
+                        data,
+                    }
+                }
+                TransferEvent::Written { .. } => {
+                    PrimarySelectionEvent::Sent { seat_id, mime_type }
+                }
+                TransferEvent::Error { error, .. } => {
+                    PrimarySelectionEvent::Error {
+                        seat_id,
+                        mime_type,
+                        error,
+                    }
+                }
+            };
+            event_source.push_event(event);
+        });
+        self.event_drain.poll_events(|event| {
+            cb(event);
+        });
+    }
+}
+
+impl Drop for PrimarySelection {
+    fn drop(&mut self) {
+        for (_, device) in &self.devices {
+            device.destroy();
+        }
+        for (_, data_source, _, _) in &self.data_sources {
+            // cancelled sources were destroyed already
+            if data_source.is_alive() {
+                data_source.destroy();
+            }
+        }
+        self.manager.destroy();
+    }
+}
+
+/// Events emitted by `PrimarySelection`
+pub enum PrimarySelectionEvent {
+    /// The primary selection contents were received
+    Get {
+        /// The seat id of the primary selection
+        seat_id: u32,
+        /// The negotiated mime type
+        mime_type: String,
+        /// The primary selection contents
+        data: Vec<u8>,
+    },
+    /// You requested your own primary selection contents
+    GetLocal {
+        /// The seat id of the primary selection
+        seat_id: u32,
+        /// The negotiated mime type
+        mime_type: String,
+        /// The primary selection contents
+        data: Vec<u8>,
+    },
+    /// The primary selection contents were sent to a client that requested
+    /// them
+    Sent {
+        /// The seat id of the primary selection
+        seat_id: u32,
+        /// The requested mime type
+        mime_type: String,
+    },
+    /// Receiving or sending the primary selection contents failed
+    Error {
+        /// The seat id of the primary selection
+        seat_id: u32,
+        /// The mime type of the transfer
+        mime_type: String,
+        /// The error
+        error: io::Error,
+    },
+}
+
+/// Handles `zwp_primary_selection_device_v1` events
+fn implement_primary_selection_device(
+    device: NewProxy<ZwpPrimarySelectionDeviceV1>,
+) -> Proxy<ZwpPrimarySelectionDeviceV1> {
+    device.implement(
+        move |event, device| {
+            let mut user_data = device
+                .user_data::<Mutex<PrimarySelectionDeviceUserData>>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            match event {
+                DeviceEvent::DataOffer { offer } => {
+                    user_data.offers.push(PrimarySelectionOffer::new(offer));
+                }
+                DeviceEvent::Selection { id } => {
+                    user_data.set_selection(id);
+                }
+            }
+        },
+        Mutex::new(PrimarySelectionDeviceUserData::new()),
+    )
+}
+
+/// `zwp_primary_selection_device_v1` user data
+struct PrimarySelectionDeviceUserData {
+    /// The current primary selection
+    selection: Option<PrimarySelectionOffer>,
+    /// Offers anounced by the compositor that were not used yet
+    offers: Vec<PrimarySelectionOffer>,
+}
+
+impl PrimarySelectionDeviceUserData {
+    fn new() -> Self {
+        PrimarySelectionDeviceUserData {
+            selection: None,
+            offers: Vec::new(),
+        }
+    }
+
+    /// Replaces the selection, the previous one gets destroyed
+    ///
+    /// Every offer is announced right before it is selected, so all other
+    /// unused offers are stale and get destroyed as well.
+    fn set_selection(
+        &mut self,
+        offer: Option<Proxy<ZwpPrimarySelectionOfferV1>>,
+    ) {
+        self.selection = offer.and_then(|offer| {
+            self.offers
+                .iter()
+                .position(|o| o.offer.equals(&offer))
+                .map(|id| self.offers.swap_remove(id))
+        });
+        self.offers.clear();
+    }
+}
+
+/// An offer for receiving the primary selection
+#[derive(Clone)]
+pub struct PrimarySelectionOffer {
+    offer: Proxy<ZwpPrimarySelectionOfferV1>,
+    mime_types: Arc<Mutex<Vec<String>>>,
+}
+
+impl PrimarySelectionOffer {
+    fn new(offer: NewProxy<ZwpPrimarySelectionOfferV1>) -> Self {
+        let mime_types = Arc::new(Mutex::new(Vec::new()));
+        // a weak handle, so that the last `PrimarySelectionOffer` clone sees
+        // itself as the last owner and destroys the offer
+        let mime_types2 = Arc::downgrade(&mime_types);
+        let offer = offer.implement(
+            move |event, _| match event {
+                OfferEvent::Offer { mime_type } => {
+                    if let Some(mime_types) = mime_types2.upgrade() {
+                        mime_types.lock().unwrap().push(mime_type);
+                    }
+                }
+            },
+            (),
+        );
+        PrimarySelectionOffer { offer, mime_types }
+    }
+
+    /// Access the list of mime types proposed by this offer
+    pub fn with_mime_types<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&[String]) -> T,
+    {
+        let mime_types = self.mime_types.lock().unwrap();
+        f(&mime_types)
+    }
+
+    /// Request to receive the data of a given mime type
+    ///
+    /// Fails if too many file descriptors were already open and a pipe
+    /// could not be created.
+    pub fn receive(&self, mime_type: String) -> Result<ReadPipe, ()> {
+        use nix::fcntl::OFlag;
+        use nix::unistd::{close, pipe2};
+        let (readfd, writefd) = pipe2(OFlag::O_CLOEXEC).map_err(|_| ())?;
+
+        self.offer.receive(mime_type, writefd);
+
+        if let Err(err) = close(writefd) {
+            eprintln!(
+                "[SCTK] Primary selection offer: failed to close write pipe: {}",
+                err
+            );
+        }
+
+        Ok(unsafe { FromRawFd::from_raw_fd(readfd) })
+    }
+}
+
+impl Drop for PrimarySelectionOffer {
+    fn drop(&mut self) {
+        // the last clone destroys the offer
+        if Arc::strong_count(&self.mime_types) == 1 {
+            self.offer.destroy();
+        }
+    }
+}
+
+/// Events of a primary selection source
+enum PrimarySelectionSourceEvent {
+    /// Write the offered data for selected mime type
+    Send {
+        /// Requested mime type
+        mime_type: String,
+        /// Pipe to write into
+        pipe: WritePipe,
+    },
+    /// The source was replaced by another one
+    Cancelled,
+}
+
+/// Handles `zwp_primary_selection_source_v1` events and forwards them
+/// to an event queue.
+fn implement_primary_selection_source(
+    source: NewProxy<ZwpPrimarySelectionSourceV1>,
+    event_queue: EventSource<PrimarySelectionSourceEvent>,
+) -> Proxy<ZwpPrimarySelectionSourceV1> {
+    source.implement(
+        move |event, source| {
+            let event = match event {
+                SourceEvent::Send { mime_type, fd } => {
+                    PrimarySelectionSourceEvent::Send {
+                        mime_type,
+                        pipe: unsafe { FromRawFd::from_raw_fd(fd) },
+                    }
+                }
+                SourceEvent::Cancelled => {
+                    source.destroy();
+                    PrimarySelectionSourceEvent::Cancelled
+                }
+            };
+            event_queue.push_event(event);
+        },
+        (),
+    )
+}
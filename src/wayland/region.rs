@@ -0,0 +1,67 @@
+//! Region handling
+use crate::wayland::compositor::{CompositorRequests, WlCompositor};
+use crate::wayland::damage::Rect;
+pub use wayland_client::protocol::wl_region::RequestsTrait as RegionRequests;
+pub use wayland_client::protocol::wl_region::WlRegion;
+use wayland_client::Proxy;
+
+/// A region built from added and subtracted rectangles
+///
+/// The operations are applied in order, so a rectangle added after a
+/// subtraction is part of the region again. An empty region contains
+/// nothing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Region {
+    operations: Vec<(bool, Rect)>,
+}
+
+impl Region {
+    /// Creates an empty `Region`
+    pub fn new() -> Self {
+        Region {
+            operations: Vec::new(),
+        }
+    }
+
+    /// Adds a rectangle to the region
+    pub fn add(mut self, rect: Rect) -> Self {
+        self.operations.push((true, rect));
+        self
+    }
+
+    /// Subtracts a rectangle from the region
+    pub fn subtract(mut self, rect: Rect) -> Self {
+        self.operations.push((false, rect));
+        self
+    }
+
+    /// Returns `true` if the point lies inside the region
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let point = Rect::new(x, y, 1, 1);
+        self.operations
+            .iter()
+            .rev()
+            .find(|(_, rect)| rect.contains(&point))
+            .map(|(add, _)| *add)
+            .unwrap_or(false)
+    }
+
+    /// Creates a `wl_region` with the rectangles of the region
+    ///
+    /// The `wl_region` can be destroyed as soon as it was used.
+    pub fn create(&self, compositor: &Proxy<WlCompositor>) -> Proxy<WlRegion> {
+        let region = compositor
+            .create_region(|region| {
+                region.implement(|event, _region| match event {}, ())
+            })
+            .unwrap();
+        for (add, rect) in &self.operations {
+            if *add {
+                region.add(rect.x, rect.y, rect.width, rect.height);
+            } else {
+                region.subtract(rect.x, rect.y, rect.width, rect.height);
+            }
+        }
+        region
+    }
+}
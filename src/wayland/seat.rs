@@ -28,10 +28,16 @@ use crate::wayland::touch::{
     implement_touch, TouchEvent, TouchRequests, WlTouch,
 };
 
+/// The highest `wl_seat` version this crate implements
+///
+/// Is the version of the `wl_seat` bindings, newer compositors may send
+/// events they can't decode.
+pub const SEAT_VERSION: u32 = 5;
+
 /// Handles `wl_seat`s
 #[derive(Clone)]
 pub struct SeatManager {
-    seats: Arc<Mutex<Vec<Proxy<WlSeat>>>>,
+    seats: Arc<Mutex<Vec<Seat>>>,
     event_drain: EventDrain<SeatManagerEvent>,
     cursor_manager: CursorManager,
     data_device_manager: Proxy<WlDataDeviceManager>,
@@ -60,6 +66,7 @@ impl SeatManager {
     ) {
         let cursor_manager = self.cursor_manager.clone();
         let data_device_manager = self.data_device_manager.clone();
+        let version = std::cmp::min(version, SEAT_VERSION);
         let seat = registry
             .bind(version, seat_id, |seat| {
                 seat.implement(
@@ -99,12 +106,12 @@ impl SeatManager {
                 )
             })
             .unwrap();
-        self.seats.lock().unwrap().push(seat);
+        self.seats.lock().unwrap().push(Seat::new(seat));
     }
 
     fn remove_seat(&self, seat_id: u32) {
         self.seats.lock().unwrap().retain(|seat| {
-            if seat.id() == seat_id && seat.version() >= 5 {
+            if seat.id() == seat_id {
                 seat.release();
             }
             seat.id() != seat_id
@@ -112,12 +119,12 @@ impl SeatManager {
     }
 
     /// A list of all current seats
-    pub fn seats(&self) -> &Arc<Mutex<Vec<Proxy<WlSeat>>>> {
-        &self.seats
+    pub fn seats(&self) -> Vec<Seat> {
+        self.seats.lock().unwrap().clone()
     }
 
-    /// The `wl_seat` with `seat_id`
-    pub fn get_seat(&self, seat_id: u32) -> Option<Proxy<WlSeat>> {
+    /// The `Seat` with `seat_id`
+    pub fn get_seat(&self, seat_id: u32) -> Option<Seat> {
         self.seats
             .lock()
             .unwrap()
@@ -128,32 +135,12 @@ impl SeatManager {
 
     /// The `Cursor` associated with `seat_id`
     pub fn get_cursor(&self, seat_id: u32) -> Option<Cursor> {
-        let seat = self.get_seat(seat_id);
-        if seat.is_none() {
-            return None;
-        }
-        seat.unwrap()
-            .user_data::<Mutex<SeatUserData>>()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .cursor()
-            .map(|cursor| cursor.clone())
+        self.get_seat(seat_id).and_then(|seat| seat.cursor())
     }
 
     /// The `wl_data_device` associated with `seat_id`
     pub fn get_data_device(&self, seat_id: u32) -> Option<DataDevice> {
-        let seat = self.get_seat(seat_id);
-        if seat.is_none() {
-            return None;
-        }
-        seat.unwrap()
-            .user_data::<Mutex<SeatUserData>>()
-            .unwrap()
-            .lock()
-            .unwrap()
-            .data_device()
-            .map(|data_device| DataDevice::new(data_device.clone()))
+        self.get_seat(seat_id).and_then(|seat| seat.data_device())
     }
 
     /// Processes it's event queues
@@ -173,6 +160,94 @@ impl SeatManager {
     }
 }
 
+/// A handle to a `wl_seat`
+#[derive(Clone)]
+pub struct Seat {
+    seat: Proxy<WlSeat>,
+}
+
+impl Seat {
+    fn new(seat: Proxy<WlSeat>) -> Self {
+        Seat { seat }
+    }
+
+    /// The id of the seat
+    pub fn id(&self) -> u32 {
+        self.seat.id()
+    }
+
+    /// The `wl_seat` protocol version the seat was bound with
+    pub fn version(&self) -> u32 {
+        self.seat.version()
+    }
+
+    /// The name of the seat
+    ///
+    /// Is empty until the compositor sent it, which it only does
+    /// starting with `wl_seat` version 2.
+    pub fn name(&self) -> String {
+        self.with_user_data(|user_data| user_data.name().to_owned())
+    }
+
+    /// The `Cursor` of the seat if it has a pointer device
+    pub fn cursor(&self) -> Option<Cursor> {
+        self.with_user_data(|user_data| user_data.cursor().cloned())
+    }
+
+    /// The `DataDevice` of the seat
+    pub fn data_device(&self) -> Option<DataDevice> {
+        self.with_user_data(|user_data| {
+            user_data
+                .data_device()
+                .map(|data_device| DataDevice::new(data_device.clone()))
+        })
+    }
+
+    /// Returns the `wl_seat`
+    pub fn proxy(&self) -> &Proxy<WlSeat> {
+        &self.seat
+    }
+
+    /// Access the `SeatUserData` of the seat
+    pub fn with_user_data<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&SeatUserData) -> T,
+    {
+        let user_data = self
+            .seat
+            .user_data::<Mutex<SeatUserData>>()
+            .unwrap()
+            .lock()
+            .unwrap();
+        f(&*user_data)
+    }
+
+    /// Releases the seat devices and the `wl_seat`
+    fn release(&self) {
+        {
+            let mut user_data = self
+                .seat
+                .user_data::<Mutex<SeatUserData>>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            user_data.drop_pointer();
+            user_data.drop_keyboard();
+            user_data.drop_touch();
+            user_data.drop_data_device();
+        }
+        if self.seat.version() >= 5 {
+            self.seat.release();
+        }
+    }
+}
+
+impl PartialEq for Seat {
+    fn eq(&self, other: &Seat) -> bool {
+        self.seat.equals(&other.seat)
+    }
+}
+
 #[derive(Clone)]
 /// Compiled information about a seat
 pub struct SeatUserData {
@@ -183,6 +258,7 @@ pub struct SeatUserData {
     keyboard: Option<Proxy<WlKeyboard>>,
     touch: Option<Proxy<WlTouch>>,
     data_device: Option<Proxy<WlDataDevice>>,
+    keyboard_focus: Option<SeatEventSource<KeyboardEvent>>,
 }
 
 impl SeatUserData {
@@ -196,9 +272,21 @@ impl SeatUserData {
             keyboard: None,
             touch: None,
             data_device: None,
+            keyboard_focus: None,
         }
     }
 
+    /// The event source following the keyboard focus
+    fn keyboard_focus(
+        &mut self,
+        seat: &Proxy<WlSeat>,
+    ) -> SeatEventSource<KeyboardEvent> {
+        if self.keyboard_focus.is_none() {
+            self.keyboard_focus = Some(SeatEventSource::new(seat.id()));
+        }
+        self.keyboard_focus.clone().unwrap()
+    }
+
     /// Returns the name of the seat
     pub fn name(&self) -> &str {
         &self.name[..]
@@ -246,9 +334,9 @@ impl SeatUserData {
 
     fn impl_keyboard(&mut self, seat: &Proxy<WlSeat>) {
         if self.keyboard.is_none() {
+            let event_queue = self.keyboard_focus(seat);
             self.keyboard = seat
                 .get_keyboard(|keyboard| {
-                    let event_queue = SeatEventSource::new(seat.id());
                     implement_keyboard(keyboard, event_queue)
                 })
                 .ok();
@@ -300,10 +388,15 @@ impl SeatUserData {
         data_device_manager: &Proxy<WlDataDeviceManager>,
     ) {
         if self.data_device.is_none() {
+            let selection_queue = self.keyboard_focus(seat).share();
             self.data_device = data_device_manager
                 .get_data_device(&seat, |data_device| {
                     let event_queue = SeatEventSource::new(seat.id());
-                    implement_data_device(data_device, event_queue)
+                    implement_data_device(
+                        data_device,
+                        event_queue,
+                        selection_queue,
+                    )
                 })
                 .ok();
         }
@@ -317,7 +410,9 @@ impl SeatUserData {
     fn drop_data_device(&mut self) {
         if self.data_device.is_some() {
             let data_device = self.data_device.take().unwrap();
-            data_device.release();
+            if data_device.version() >= 2 {
+                data_device.release();
+            }
         }
     }
 }
@@ -379,7 +474,11 @@ pub enum SeatEvent {
 /// Seat event source specialized for different seat devices
 pub struct SeatEventSource<T> {
     seat_id: u32,
-    event_source: Arc<Mutex<Option<EventSource<SurfaceEvent>>>>,
+    /// The entered surface's event source and the surface id if it is a
+    /// subsurface
+    event_source: Arc<Mutex<Option<(EventSource<SurfaceEvent>, Option<u32>)>>>,
+    /// Events queued before a surface was entered
+    pending: Arc<Mutex<Vec<SurfaceEvent>>>,
     _type: PhantomData<T>,
 }
 
@@ -389,36 +488,92 @@ impl<T> SeatEventSource<T> {
         SeatEventSource {
             seat_id,
             event_source: Arc::new(Mutex::new(None)),
+            pending: Arc::new(Mutex::new(Vec::new())),
+            _type: PhantomData,
+        }
+    }
+
+    /// Creates a `SeatEventSource` for another seat device that queues
+    /// its events to the same surface
+    pub fn share<U>(&self) -> SeatEventSource<U> {
+        SeatEventSource {
+            seat_id: self.seat_id,
+            event_source: self.event_source.clone(),
+            pending: self.pending.clone(),
             _type: PhantomData,
         }
     }
 
     /// The seat device entered a surface
+    ///
+    /// Events queued before the first surface was entered are delivered
+    /// to it. Events of subsurfaces are delivered to their root surface.
     pub fn enter_surface(&mut self, surface: &Proxy<WlSurface>) {
-        let new_event_source = {
+        let (new_event_source, surface_id) = {
             let surface_user_data = surface
                 .user_data::<Mutex<SurfaceUserData>>()
                 .unwrap()
                 .lock()
                 .unwrap();
-            surface_user_data.event_source.clone()
+            match surface_user_data.root_event_source {
+                Some(ref event_source) => {
+                    (event_source.clone(), Some(surface.id()))
+                }
+                None => (surface_user_data.event_source.clone(), None),
+            }
         };
+        for event in self.pending.lock().unwrap().drain(..) {
+            new_event_source.push_event(event);
+        }
         let mut event_source = self.event_source.lock().unwrap();
-        *event_source = Some(new_event_source);
+        *event_source = Some((new_event_source, surface_id));
     }
 
     fn _queue_event(&self, event: SeatEvent) {
-        let event = SurfaceEvent::Seat {
-            seat_id: self.seat_id,
-            event,
-        };
         let event_source = self.event_source.lock().unwrap();
-        if event_source.is_some() {
-            event_source.as_ref().unwrap().push_event(event);
+        match *event_source {
+            Some((ref event_source, Some(surface_id))) => {
+                event_source.push_event(SurfaceEvent::SubsurfaceSeat {
+                    surface_id,
+                    seat_id: self.seat_id,
+                    event,
+                });
+            }
+            Some((ref event_source, None)) => {
+                event_source.push_event(SurfaceEvent::Seat {
+                    seat_id: self.seat_id,
+                    event,
+                });
+            }
+            None => {
+                let mut pending = self.pending.lock().unwrap();
+                // only the latest selection is of interest
+                if is_selection(&event) {
+                    pending.retain(|event| match event {
+                        SurfaceEvent::Seat { event, .. } => {
+                            !is_selection(event)
+                        }
+                        _ => true,
+                    });
+                }
+                pending.push(SurfaceEvent::Seat {
+                    seat_id: self.seat_id,
+                    event,
+                });
+            }
         }
     }
 }
 
+fn is_selection(event: &SeatEvent) -> bool {
+    match event {
+        SeatEvent::DataDevice {
+            event: DataDeviceEvent::Selection { .. },
+        } => true,
+        _ => false,
+    }
+}
+
 impl SeatEventSource<PointerEvent> {
     /// Queue a pointer event to a seat event source
     pub fn queue_event(&self, event: PointerEvent) {
@@ -0,0 +1,189 @@
+//! Subsurface tree handling
+use crate::wayland::surface::{
+    SubsurfaceRequests, SurfaceEvent, SurfaceManager, SurfaceRequests,
+    SurfaceUserData, WlSubsurface, WlSurface,
+};
+use std::sync::Mutex;
+use wayland_client::Proxy;
+
+/// A subsurface owning its `wl_surface` and its child subsurfaces
+///
+/// Seat events of the subsurface are routed to the root surface of the
+/// tree as `SurfaceEvent::SubsurfaceSeat`. Dropping a `Subsurface` destroys
+/// its children.
+pub struct Subsurface {
+    surface_manager: SurfaceManager,
+    surface: Proxy<WlSurface>,
+    subsurface: Proxy<WlSubsurface>,
+    children: Vec<Subsurface>,
+    position: (i32, i32),
+    sync: bool,
+}
+
+impl Subsurface {
+    /// Creates a new `Subsurface` of `parent`
+    ///
+    /// The subsurface starts at position `(0, 0)` in synchronized mode.
+    pub fn new(
+        surface_manager: &SurfaceManager,
+        parent: &Proxy<WlSurface>,
+    ) -> Self {
+        let surface = surface_manager.create_surface();
+        let subsurface = surface_manager.create_subsurface(&surface, parent);
+        let root_event_source = {
+            let parent_user_data = parent
+                .user_data::<Mutex<SurfaceUserData>>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            parent_user_data
+                .root_event_source
+                .clone()
+                .unwrap_or_else(|| parent_user_data.event_source.clone())
+        };
+        surface
+            .user_data::<Mutex<SurfaceUserData>>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .root_event_source = Some(root_event_source);
+        Subsurface {
+            surface_manager: surface_manager.clone(),
+            surface,
+            subsurface,
+            children: Vec::new(),
+            position: (0, 0),
+            sync: true,
+        }
+    }
+
+    /// Returns the `wl_surface`
+    pub fn surface(&self) -> &Proxy<WlSurface> {
+        &self.surface
+    }
+
+    /// Returns the `wl_subsurface`
+    pub fn subsurface(&self) -> &Proxy<WlSubsurface> {
+        &self.subsurface
+    }
+
+    /// The id of the `wl_surface`
+    ///
+    /// Identifies the subsurface in `SurfaceEvent::SubsurfaceSeat`.
+    pub fn id(&self) -> u32 {
+        self.surface.id()
+    }
+
+    /// The position relative to the parent surface
+    pub fn position(&self) -> (i32, i32) {
+        self.position
+    }
+
+    /// Moves the subsurface relative to the parent surface
+    ///
+    /// Takes effect on the next commit of the parent surface.
+    pub fn set_position(&mut self, x: i32, y: i32) {
+        self.position = (x, y);
+        self.subsurface.set_position(x, y);
+    }
+
+    /// Stacks the subsurface directly above `sibling`
+    ///
+    /// `sibling` is the parent surface or another subsurface of it.
+    pub fn place_above(&self, sibling: &Proxy<WlSurface>) {
+        self.subsurface.place_above(sibling);
+    }
+
+    /// Stacks the subsurface directly below `sibling`
+    ///
+    /// `sibling` is the parent surface or another subsurface of it.
+    pub fn place_below(&self, sibling: &Proxy<WlSurface>) {
+        self.subsurface.place_below(sibling);
+    }
+
+    /// Returns `true` if commits are applied with the parent's commit
+    pub fn is_sync(&self) -> bool {
+        self.sync
+    }
+
+    /// Applies commits together with the next commit of the parent
+    pub fn set_sync(&mut self) {
+        self.sync = true;
+        self.subsurface.set_sync();
+    }
+
+    /// Applies commits immediately
+    ///
+    /// A subsurface still behaves synchronized if one of its ancestors is.
+    pub fn set_desync(&mut self) {
+        self.sync = false;
+        self.subsurface.set_desync();
+    }
+
+    /// Creates a child subsurface
+    pub fn add_child(&mut self) -> &mut Subsurface {
+        let child = Subsurface::new(&self.surface_manager, &self.surface);
+        self.children.push(child);
+        self.children.last_mut().unwrap()
+    }
+
+    /// Destroys the child subsurface with `id`
+    pub fn remove_child(&mut self, id: u32) {
+        self.children.retain(|child| child.id() != id);
+    }
+
+    /// The child subsurfaces from bottom to top as created
+    pub fn children(&self) -> &[Subsurface] {
+        &self.children
+    }
+
+    /// The child subsurfaces
+    pub fn children_mut(&mut self) -> &mut [Subsurface] {
+        &mut self.children
+    }
+
+    /// Finds the subsurface with `id` in the tree
+    pub fn find(&self, id: u32) -> Option<&Subsurface> {
+        if self.id() == id {
+            return Some(self);
+        }
+        self.children
+            .iter()
+            .filter_map(|child| child.find(id))
+            .next()
+    }
+
+    /// Commits the children depth first and then the subsurface
+    ///
+    /// The state of synchronized children is cached until the subsurface
+    /// is committed, so the whole tree is updated atomically.
+    pub fn commit(&self) {
+        for child in &self.children {
+            child.commit();
+        }
+        self.surface.commit();
+    }
+
+    /// Polls the events of the `wl_surface`
+    ///
+    /// Seat events are delivered to the root surface instead.
+    pub fn poll_events<F: FnMut(SurfaceEvent, &Subsurface)>(&self, mut cb: F) {
+        let surface_user_data = self
+            .surface
+            .user_data::<Mutex<SurfaceUserData>>()
+            .unwrap()
+            .lock()
+            .unwrap();
+        surface_user_data.poll_events(|event, _user_data| {
+            cb(event, self);
+        });
+    }
+}
+
+impl Drop for Subsurface {
+    fn drop(&mut self) {
+        self.children.clear();
+        self.subsurface.destroy();
+        self.surface.destroy();
+    }
+}
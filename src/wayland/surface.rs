@@ -2,9 +2,12 @@
 use crate::wayland::compositor::{CompositorRequests, WlCompositor};
 use crate::wayland::compositor::{SubcompositorRequests, WlSubcompositor};
 use crate::wayland::event_queue::{EventDrain, EventQueue, EventSource};
-use crate::wayland::output::{OutputUserData, WlOutput};
+use crate::wayland::output::{OutputUserData, Transform, WlOutput};
+use crate::wayland::presentation::PresentationFeedback;
+use crate::wayland::region::{Region, RegionRequests};
 use crate::wayland::seat::SeatEvent;
 use std::sync::{Arc, Mutex};
+use wayland_client::protocol::wl_callback::Event as CallbackEvent;
 pub use wayland_client::protocol::wl_subsurface::RequestsTrait as SubsurfaceRequests;
 pub use wayland_client::protocol::wl_subsurface::WlSubsurface;
 use wayland_client::protocol::wl_surface::Event;
@@ -12,6 +15,121 @@ pub use wayland_client::protocol::wl_surface::RequestsTrait as SurfaceRequests;
 pub use wayland_client::protocol::wl_surface::WlSurface;
 use wayland_client::Proxy;
 
+/// Prepares `surface` for a buffer with `logical_size` at the surface scale
+///
+/// Returns the size of the buffer in pixels and sets the buffer scale to
+/// the scale factor of the surface. Call it before attaching the buffer.
+pub fn set_buffer_size(
+    surface: &Proxy<WlSurface>,
+    logical_size: (u32, u32),
+) -> (u32, u32) {
+    let user_data = surface
+        .user_data::<Mutex<SurfaceUserData>>()
+        .unwrap()
+        .lock()
+        .unwrap();
+    let (width, height) = logical_size;
+    // the buffer is rotated relative to the surface
+    let (buffer_width, buffer_height) = match user_data.buffer_transform {
+        Transform::_90
+        | Transform::_270
+        | Transform::Flipped90
+        | Transform::Flipped270 => (height, width),
+        _ => (width, height),
+    };
+    let scale_factor = user_data.scale_factor;
+    surface.set_buffer_scale(scale_factor as i32);
+    (buffer_width * scale_factor, buffer_height * scale_factor)
+}
+
+/// Sets the transform the content of the buffers of `surface` is rendered
+/// with
+///
+/// Rendering with `SurfaceUserData::preferred_transform` lets the
+/// compositor show the buffer without rotating it. Call it before
+/// `set_buffer_size`, takes effect on the next commit.
+pub fn set_buffer_transform(surface: &Proxy<WlSurface>, transform: Transform) {
+    let mut user_data = surface
+        .user_data::<Mutex<SurfaceUserData>>()
+        .unwrap()
+        .lock()
+        .unwrap();
+    if user_data.buffer_transform != transform && surface.version() >= 2 {
+        user_data.buffer_transform = transform;
+        surface.set_buffer_transform(transform);
+    }
+}
+
+/// Requests a frame callback for the next commit of `surface`
+///
+/// A `SurfaceEvent::Frame` is emitted when it is a good time to draw the
+/// next frame. Only one callback is pending at a time, further requests are
+/// coalesced until it is done. Call it before committing a new buffer.
+pub fn request_frame(surface: &Proxy<WlSurface>) {
+    let mut user_data = surface
+        .user_data::<Mutex<SurfaceUserData>>()
+        .unwrap()
+        .lock()
+        .unwrap();
+    if user_data.frame_pending {
+        return;
+    }
+    user_data.frame_pending = true;
+    let surface2 = surface.clone();
+    surface
+        .frame(move |callback| {
+            callback.implement(
+                move |event, _callback| match event {
+                    CallbackEvent::Done { callback_data } => {
+                        let mut user_data = surface2
+                            .user_data::<Mutex<SurfaceUserData>>()
+                            .unwrap()
+                            .lock()
+                            .unwrap();
+                        user_data.frame_pending = false;
+                        user_data.event_source.push_event(
+                            SurfaceEvent::Frame {
+                                time: callback_data,
+                            },
+                        );
+                    }
+                },
+                (),
+            )
+        })
+        .unwrap();
+}
+
+/// Marks `surface` as needing a redraw
+///
+/// Requests are coalesced, see `take_redraw`.
+pub fn request_redraw(surface: &Proxy<WlSurface>) {
+    surface
+        .user_data::<Mutex<SurfaceUserData>>()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .needs_redraw = true;
+}
+
+/// Returns `true` if `surface` should be redrawn now and clears the flag
+///
+/// Is `true` if a redraw was requested and no frame callback is pending,
+/// so a hidden surface isn't redrawn until the compositor asks for the
+/// next frame.
+pub fn take_redraw(surface: &Proxy<WlSurface>) -> bool {
+    let mut user_data = surface
+        .user_data::<Mutex<SurfaceUserData>>()
+        .unwrap()
+        .lock()
+        .unwrap();
+    let redraw = user_data.needs_redraw();
+    if redraw {
+        user_data.needs_redraw = false;
+    }
+    redraw
+}
+
 #[derive(Clone)]
 /// Handles `wl_surface`s
 pub struct SurfaceManager {
@@ -78,9 +196,42 @@ impl SurfaceManager {
             .unwrap()
     }
 
+    /// Sets the region of `surface` that accepts input
+    ///
+    /// `None` accepts input on the whole surface, an empty region makes
+    /// the surface click-through. Takes effect on the next commit.
+    pub fn set_input_region(
+        &self,
+        surface: &Proxy<WlSurface>,
+        region: Option<&Region>,
+    ) {
+        let region = region.map(|region| region.create(&self.compositor));
+        surface.set_input_region(region.as_ref());
+        if let Some(region) = region {
+            region.destroy();
+        }
+    }
+
+    /// Sets the region of `surface` that is opaque
+    ///
+    /// Lets the compositor skip drawing what is behind it. `None` marks the
+    /// whole surface as translucent. Takes effect on the next commit.
+    pub fn set_opaque_region(
+        &self,
+        surface: &Proxy<WlSurface>,
+        region: Option<&Region>,
+    ) {
+        let region = region.map(|region| region.create(&self.compositor));
+        surface.set_opaque_region(region.as_ref());
+        if let Some(region) = region {
+            region.destroy();
+        }
+    }
+
     /// Processes it's event queue
     pub fn handle_events(&self) {
-        let surfaces = self.surfaces.lock().unwrap();
+        let mut surfaces = self.surfaces.lock().unwrap();
+        surfaces.retain(|surface| surface.is_alive());
         self.event_drain.poll_events(|event| match event {
             SurfaceManagerEvent::OutputLeave { output } => {
                 for surface in &*surfaces {
@@ -102,6 +253,16 @@ impl SurfaceManager {
                         .update_scale_factor();
                 }
             }
+            SurfaceManagerEvent::OutputTransform { .. } => {
+                for surface in &*surfaces {
+                    surface
+                        .user_data::<Mutex<SurfaceUserData>>()
+                        .unwrap()
+                        .lock()
+                        .unwrap()
+                        .update_transform();
+                }
+            }
         });
     }
 }
@@ -110,7 +271,13 @@ impl SurfaceManager {
 pub struct SurfaceUserData {
     pub(crate) event_source: EventSource<SurfaceEvent>,
     event_drain: EventDrain<SurfaceEvent>,
+    /// Receives the seat events of a subsurface
+    pub(crate) root_event_source: Option<EventSource<SurfaceEvent>>,
     scale_factor: u32,
+    transform: Transform,
+    buffer_transform: Transform,
+    frame_pending: bool,
+    needs_redraw: bool,
     outputs: Vec<Proxy<WlOutput>>,
 }
 
@@ -121,19 +288,50 @@ impl SurfaceUserData {
         SurfaceUserData {
             event_source: source,
             event_drain: drain,
+            root_event_source: None,
             scale_factor: 1,
+            transform: Transform::Normal,
+            buffer_transform: Transform::Normal,
+            frame_pending: false,
+            needs_redraw: false,
             outputs: Vec::new(),
         }
     }
 
+    /// The integer scale factor of the surface
+    pub fn scale_factor(&self) -> u32 {
+        self.scale_factor
+    }
+
+    /// The transform the compositor prefers buffers to be rendered with
+    ///
+    /// Is the transform of the outputs the surface is on if they all agree
+    /// and `Normal` otherwise.
+    pub fn preferred_transform(&self) -> Transform {
+        self.transform
+    }
+
+    /// The transform set with `set_buffer_transform`
+    pub fn buffer_transform(&self) -> Transform {
+        self.buffer_transform
+    }
+
+    /// Returns `true` if a redraw was requested and no frame callback is
+    /// pending
+    pub fn needs_redraw(&self) -> bool {
+        self.needs_redraw && !self.frame_pending
+    }
+
     pub(crate) fn enter(&mut self, output: Proxy<WlOutput>) {
         self.outputs.push(output);
         self.update_scale_factor();
+        self.update_transform();
     }
 
     pub(crate) fn leave(&mut self, output: &Proxy<WlOutput>) {
         self.outputs.retain(|output2| !output.equals(output2));
         self.update_scale_factor();
+        self.update_transform();
     }
 
     pub(crate) fn update_scale_factor(&mut self) {
@@ -154,6 +352,34 @@ impl SurfaceUserData {
         }
     }
 
+    pub(crate) fn update_transform(&mut self) {
+        let transforms = self
+            .outputs
+            .iter()
+            .map(|output| {
+                output
+                    .user_data::<Mutex<OutputUserData>>()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .transform
+            })
+            .collect::<Vec<_>>();
+        let transform = match transforms.first() {
+            Some(transform)
+                if transforms.iter().all(|other| other == transform) =>
+            {
+                *transform
+            }
+            _ => Transform::Normal,
+        };
+        if self.transform != transform {
+            self.transform = transform;
+            self.event_source
+                .push_event(SurfaceEvent::PreferredTransform { transform });
+        }
+    }
+
     /// Process it's event queue
     pub fn poll_events<F: FnMut(SurfaceEvent, &SurfaceUserData)>(
         &self,
@@ -175,6 +401,13 @@ pub enum SurfaceManagerEvent {
         /// New scale factor
         factor: u32,
     },
+    /// Output transform changed
+    OutputTransform {
+        /// The `wl_output`
+        output: Proxy<WlOutput>,
+        /// New transform
+        transform: Transform,
+    },
     /// Output was disconnected
     OutputLeave {
         /// The `wl_output`
@@ -190,6 +423,27 @@ pub enum SurfaceEvent {
         /// New scale factor
         scale_factor: u32,
     },
+    /// The transform of the outputs the surface is on has changed
+    ///
+    /// Render with it and pass it to `set_buffer_transform`.
+    PreferredTransform {
+        /// The new preferred transform
+        transform: Transform,
+    },
+    /// It is a good time to draw the next frame
+    ///
+    /// Emitted once per `request_frame`.
+    Frame {
+        /// A timestamp in milliseconds with undefined base
+        time: u32,
+    },
+    /// Presentation feedback requested with `Presentation::feedback`
+    Presentation {
+        /// The id returned by `Presentation::feedback`
+        feedback_id: u32,
+        /// How the commit was presented
+        feedback: PresentationFeedback,
+    },
     /// A seat event was received
     Seat {
         /// Seat that sent the event
@@ -197,4 +451,13 @@ pub enum SurfaceEvent {
         /// The sent event
         event: SeatEvent,
     },
+    /// A seat event was received by a subsurface of this surface
+    SubsurfaceSeat {
+        /// The id of the subsurface's `wl_surface`
+        surface_id: u32,
+        /// Seat that sent the event
+        seat_id: u32,
+        /// The sent event
+        event: SeatEvent,
+    },
 }
@@ -0,0 +1,241 @@
+//! Non-blocking pipe transfers for dnd and clipboard handling
+//!
+//! Reading or writing a pipe in a blocking way from the event loop freezes
+//! the application while the other client is slow, and deadlocks it when
+//! the other end is the application itself. The `TransferEngine` sets the
+//! pipes to `O_NONBLOCK` and moves the data in chunks whenever a pipe is
+//! ready.
+use crate::wayland::pipe::{AsRawFd, RawFd, ReadPipe, WritePipe};
+use nix::poll::{poll, EventFlags, PollFd};
+use std::io::{self, Read, Write};
+
+/// The maximum number of bytes moved per pipe and dispatch
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Identifies a transfer of a `TransferEngine`
+pub type TransferId = u64;
+
+enum Transfer {
+    Read {
+        id: TransferId,
+        pipe: ReadPipe,
+        data: Vec<u8>,
+    },
+    Write {
+        id: TransferId,
+        pipe: WritePipe,
+        data: Vec<u8>,
+        offset: usize,
+    },
+}
+
+impl Transfer {
+    fn id(&self) -> TransferId {
+        match self {
+            Transfer::Read { id, .. } | Transfer::Write { id, .. } => *id,
+        }
+    }
+
+    fn fd(&self) -> RawFd {
+        match self {
+            Transfer::Read { pipe, .. } => pipe.as_raw_fd(),
+            Transfer::Write { pipe, .. } => pipe.as_raw_fd(),
+        }
+    }
+
+    fn poll_fd(&self) -> PollFd {
+        match self {
+            Transfer::Read { .. } => PollFd::new(self.fd(), EventFlags::POLLIN),
+            Transfer::Write { .. } => {
+                PollFd::new(self.fd(), EventFlags::POLLOUT)
+            }
+        }
+    }
+
+    /// Moves the next chunk, returns `Some` when the transfer is complete
+    fn process(&mut self) -> Option<TransferEvent> {
+        let id = self.id();
+        match self {
+            Transfer::Read { pipe, data, .. } => {
+                let mut buf = vec![0; CHUNK_SIZE];
+                match pipe.read(&mut buf) {
+                    Ok(0) => Some(TransferEvent::Read {
+                        id,
+                        data: std::mem::replace(data, Vec::new()),
+                    }),
+                    Ok(n) => {
+                        data.extend_from_slice(&buf[..n]);
+                        None
+                    }
+                    Err(ref err) if is_transient(err) => None,
+                    Err(error) => Some(TransferEvent::Error { id, error }),
+                }
+            }
+            Transfer::Write {
+                pipe, data, offset, ..
+            } => {
+                let end = std::cmp::min(*offset + CHUNK_SIZE, data.len());
+                match pipe.write(&data[*offset..end]) {
+                    Ok(n) => {
+                        *offset += n;
+                        if *offset == data.len() {
+                            Some(TransferEvent::Written { id })
+                        } else {
+                            None
+                        }
+                    }
+                    Err(ref err) if is_transient(err) => None,
+                    Err(error) => Some(TransferEvent::Error { id, error }),
+                }
+            }
+        }
+    }
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => true,
+        _ => false,
+    }
+}
+
+/// Drives non-blocking pipe transfers
+pub struct TransferEngine {
+    transfers: Vec<Transfer>,
+    next_id: TransferId,
+}
+
+impl TransferEngine {
+    /// Creates a new `TransferEngine`
+    pub fn new() -> Self {
+        TransferEngine {
+            transfers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    fn next_id(&mut self) -> TransferId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Reads `pipe` until the writer closes it
+    ///
+    /// Emits a `TransferEvent::Read` with all the data once done.
+    pub fn read(&mut self, pipe: ReadPipe) -> io::Result<TransferId> {
+        pipe.set_nonblocking()?;
+        let id = self.next_id();
+        self.transfers.push(Transfer::Read {
+            id,
+            pipe,
+            data: Vec::new(),
+        });
+        Ok(id)
+    }
+
+    /// Writes `data` to `pipe` and closes it
+    ///
+    /// Emits a `TransferEvent::Written` once done.
+    pub fn write(
+        &mut self,
+        pipe: WritePipe,
+        data: Vec<u8>,
+    ) -> io::Result<TransferId> {
+        pipe.set_nonblocking()?;
+        let id = self.next_id();
+        self.transfers.push(Transfer::Write {
+            id,
+            pipe,
+            data,
+            offset: 0,
+        });
+        Ok(id)
+    }
+
+    /// Returns `true` if there are no pending transfers
+    pub fn is_empty(&self) -> bool {
+        self.transfers.is_empty()
+    }
+
+    /// The file descriptors of the pending transfers
+    ///
+    /// Add them to your event loop to know when to call `dispatch`.
+    pub fn fds(&self) -> Vec<RawFd> {
+        self.transfers
+            .iter()
+            .map(|transfer| transfer.fd())
+            .collect()
+    }
+
+    /// Moves a chunk on every pipe that is ready
+    ///
+    /// Waits at most `timeout` milliseconds for a pipe to become ready,
+    /// a `timeout` of zero never blocks and a negative one blocks until a
+    /// pipe is ready.
+    pub fn dispatch<F: FnMut(TransferEvent)>(
+        &mut self,
+        timeout: i32,
+        mut cb: F,
+    ) {
+        if self.transfers.is_empty() {
+            return;
+        }
+        let mut poll_fds = self
+            .transfers
+            .iter()
+            .map(|transfer| transfer.poll_fd())
+            .collect::<Vec<_>>();
+        if let Err(err) = poll(&mut poll_fds, timeout) {
+            eprintln!("[SCTK] Transfer: poll failed: {}", err);
+            return;
+        }
+        let ready = poll_fds.iter().map(|poll_fd| match poll_fd.revents() {
+            Some(revents) => !revents.is_empty(),
+            None => false,
+        });
+        let mut done = Vec::new();
+        for (transfer, ready) in self.transfers.iter_mut().zip(ready) {
+            if !ready {
+                continue;
+            }
+            if let Some(event) = transfer.process() {
+                done.push(transfer.id());
+                cb(event);
+            }
+        }
+        // dropping the transfer closes the pipe
+        self.transfers
+            .retain(|transfer| !done.contains(&transfer.id()));
+    }
+}
+
+impl Default for TransferEngine {
+    fn default() -> Self {
+        TransferEngine::new()
+    }
+}
+
+/// Events emitted by a `TransferEngine`
+#[derive(Debug)]
+pub enum TransferEvent {
+    /// A read transfer completed
+    Read {
+        /// The transfer id
+        id: TransferId,
+        /// The data that was read
+        data: Vec<u8>,
+    },
+    /// A write transfer completed
+    Written {
+        /// The transfer id
+        id: TransferId,
+    },
+    /// A transfer failed and was aborted
+    Error {
+        /// The transfer id
+        id: TransferId,
+        /// The error
+        error: io::Error,
+    },
+}
@@ -0,0 +1,383 @@
+//! Handles `xdg_popup`s and `xdg_positioner`s.
+//!
+//! Like toplevels, popups acknowledge their configures in `XdgPopup::commit`
+//! or `XdgPopup::ack_configure`, not when the `wl_surface` is committed.
+use crate::wayland::damage::Rect;
+use crate::wayland::event_queue::{EventDrain, EventQueue};
+use crate::wayland::layer_shell::{LayerSurfaceRequests, ZwlrLayerSurfaceV1};
+use crate::wayland::output::Transform;
+use crate::wayland::presentation::PresentationFeedback;
+use crate::wayland::region::Region;
+use crate::wayland::seat::{SeatEvent, SeatManager};
+use crate::wayland::surface::{
+    SurfaceEvent, SurfaceManager, SurfaceRequests, SurfaceUserData, WlSurface,
+};
+use crate::wayland::xdg_shell::XdgSurfaceHandle;
+use std::sync::{Arc, Mutex};
+use wayland_client::Proxy;
+use wayland_protocols::xdg_shell::client::{
+    xdg_popup::Event as XdgPopupEvent_,
+    xdg_surface::RequestsTrait as XdgSurfaceRequests, xdg_surface::XdgSurface,
+    xdg_wm_base::RequestsTrait as XdgShellRequests, xdg_wm_base::XdgWmBase,
+};
+pub use wayland_protocols::xdg_shell::client::{
+    xdg_popup::RequestsTrait as XdgPopupRequests,
+    xdg_popup::XdgPopup as XdgPopupProxy, xdg_positioner::Anchor,
+    xdg_positioner::ConstraintAdjustment, xdg_positioner::Gravity,
+    xdg_positioner::RequestsTrait as XdgPositionerRequests,
+    xdg_positioner::XdgPositioner,
+};
+
+/// Describes where a popup is placed relative to its parent
+///
+/// The popup of `size` is placed at the `anchor` of `anchor_rect` and
+/// extends in the direction of `gravity`. If it doesn't fit on the output
+/// the compositor adjusts it as allowed by `constraint_adjustment`.
+#[derive(Clone, Debug)]
+pub struct Positioner {
+    size: (i32, i32),
+    anchor_rect: Rect,
+    anchor: Anchor,
+    gravity: Gravity,
+    constraint_adjustment: ConstraintAdjustment,
+    offset: (i32, i32),
+}
+
+impl Positioner {
+    /// Creates a new `Positioner`
+    ///
+    /// `anchor_rect` is in the window geometry coordinates of the parent.
+    /// The popup is centered on `anchor_rect` and never adjusted by default.
+    ///
+    /// Fails if `size` isn't positive or `anchor_rect` has a negative size,
+    /// the compositor would disconnect the client for it.
+    pub fn new(size: (i32, i32), anchor_rect: Rect) -> Result<Self, ()> {
+        if size.0 <= 0
+            || size.1 <= 0
+            || anchor_rect.width < 0
+            || anchor_rect.height < 0
+        {
+            return Err(());
+        }
+        Ok(Positioner {
+            size,
+            anchor_rect,
+            anchor: Anchor::None,
+            gravity: Gravity::None,
+            constraint_adjustment: ConstraintAdjustment::empty(),
+            offset: (0, 0),
+        })
+    }
+
+    /// Sets the point of the anchor rectangle the popup is placed at
+    pub fn with_anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// Sets the direction the popup extends to from the anchor point
+    pub fn with_gravity(mut self, gravity: Gravity) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// Sets how the compositor may adjust a popup that doesn't fit
+    pub fn with_constraint_adjustment(
+        mut self,
+        constraint_adjustment: ConstraintAdjustment,
+    ) -> Self {
+        self.constraint_adjustment = constraint_adjustment;
+        self
+    }
+
+    /// Moves the popup away from the anchor point
+    pub fn with_offset(mut self, x: i32, y: i32) -> Self {
+        self.offset = (x, y);
+        self
+    }
+
+    /// Creates a `xdg_positioner` with the properties of the positioner
+    ///
+    /// The `xdg_positioner` can be destroyed as soon as it was used.
+    pub fn create(&self, xdg_shell: &Proxy<XdgWmBase>) -> Proxy<XdgPositioner> {
+        let positioner = xdg_shell
+            .create_positioner(|positioner| {
+                positioner.implement(|event, _positioner| match event {}, ())
+            })
+            .unwrap();
+        positioner.set_size(self.size.0, self.size.1);
+        let rect = &self.anchor_rect;
+        positioner.set_anchor_rect(rect.x, rect.y, rect.width, rect.height);
+        positioner.set_anchor(self.anchor);
+        positioner.set_gravity(self.gravity);
+        positioner.set_constraint_adjustment(self.constraint_adjustment.bits());
+        positioner.set_offset(self.offset.0, self.offset.1);
+        positioner
+    }
+}
+
+/// Creates a popup of `parent` or of the layer surface `layer_parent`
+pub(crate) fn create_popup(
+    xdg_shell: &Proxy<XdgWmBase>,
+    surface_manager: &SurfaceManager,
+    seat_manager: &SeatManager,
+    parent: Option<&Proxy<XdgSurface>>,
+    layer_parent: Option<&Proxy<ZwlrLayerSurfaceV1>>,
+    positioner: &Positioner,
+) -> XdgPopup {
+    let (source, drain) = EventQueue::new();
+    let surface = surface_manager.create_surface();
+    // the popup placement is sent before the configure it belongs to
+    let pending = Arc::new(Mutex::new(((0, 0), (0, 0))));
+    let source2 = source.clone();
+    let pending2 = pending.clone();
+    let xdg_surface =
+        XdgSurfaceHandle::new(xdg_shell, &surface, move |serial| {
+            let (position, size) = *pending2.lock().unwrap();
+            source2.push_event(XdgPopupEvent::Configure {
+                serial,
+                position,
+                size,
+            });
+        });
+    let xdg_positioner = positioner.create(xdg_shell);
+    let xdg_popup = xdg_surface
+        .xdg_surface()
+        .get_popup(parent, &xdg_positioner, |xdg_popup| {
+            xdg_popup.implement(
+                move |event, _xdg_popup| match event {
+                    XdgPopupEvent_::Configure {
+                        x,
+                        y,
+                        width,
+                        height,
+                    } => {
+                        *pending.lock().unwrap() =
+                            ((x, y), (width as u32, height as u32));
+                    }
+                    XdgPopupEvent_::PopupDone => {
+                        source.push_event(XdgPopupEvent::Done);
+                    }
+                },
+                (),
+            )
+        })
+        .unwrap();
+    xdg_positioner.destroy();
+    if let Some(layer_surface) = layer_parent {
+        layer_surface.get_popup(&xdg_popup);
+    }
+    surface.commit();
+    XdgPopup {
+        surface_manager: surface_manager.clone(),
+        seat_manager: seat_manager.clone(),
+        surface,
+        xdg_surface,
+        xdg_popup,
+        event_drain: drain,
+    }
+}
+
+/// A popup for menus and tooltips
+///
+/// The popup is dismissed by the compositor with `XdgPopupEvent::Done`,
+/// drop it then.
+pub struct XdgPopup {
+    surface_manager: SurfaceManager,
+    seat_manager: SeatManager,
+    surface: Proxy<WlSurface>,
+    xdg_surface: XdgSurfaceHandle,
+    xdg_popup: Proxy<XdgPopupProxy>,
+    event_drain: EventDrain<XdgPopupEvent>,
+}
+
+impl XdgPopup {
+    /// Returns the `wl_surface`
+    pub fn surface(&self) -> &Proxy<WlSurface> {
+        &self.surface
+    }
+
+    /// Returns the `xdg_surface`
+    ///
+    /// Pass it to `XdgShell::create_popup` to create a nested popup.
+    pub fn xdg_surface(&self) -> &Proxy<XdgSurface> {
+        self.xdg_surface.xdg_surface()
+    }
+
+    /// Returns the `xdg_popup`
+    pub fn xdg_popup(&self) -> &Proxy<XdgPopupProxy> {
+        &self.xdg_popup
+    }
+
+    /// Grabs the keyboard and pointer of the seat with `seat_id`
+    ///
+    /// `serial` is the serial of the user input that opened the popup.
+    /// Call it before attaching the first buffer. The popup is dismissed
+    /// when the user clicks outside of the client's surfaces.
+    pub fn grab(&self, seat_id: u32, serial: u32) {
+        match self.seat_manager.get_seat(seat_id) {
+            Some(seat) => self.xdg_popup.grab(seat.proxy(), serial),
+            None => eprintln!("[SCTK] XdgPopup: unknown seat {}", seat_id),
+        }
+    }
+
+    /// Acknowledges the configure with `serial`
+    ///
+    /// The next commit applies the state of that configure.
+    pub fn ack_configure(&self, serial: u32) {
+        self.xdg_surface.ack_configure(serial);
+    }
+
+    /// Commits the surface
+    ///
+    /// Acknowledges the last configure first if that wasn't done with
+    /// `ack_configure`. Use it instead of committing the `wl_surface`.
+    pub fn commit(&self) {
+        self.xdg_surface.commit();
+    }
+
+    /// Sets the part of the surface that is the popup
+    ///
+    /// The positioner places the window geometry, so shadows can extend
+    /// outside of it. Takes effect on the next commit.
+    pub fn set_window_geometry(&self, geometry: Rect) {
+        self.xdg_surface.set_window_geometry(geometry);
+    }
+
+    /// Polls the events from the event queue
+    pub fn poll_events<F: FnMut(XdgPopupEvent, &XdgPopup)>(&self, mut cb: F) {
+        {
+            let surface_user_data = self
+                .surface
+                .user_data::<Mutex<SurfaceUserData>>()
+                .unwrap()
+                .lock()
+                .unwrap();
+            surface_user_data.poll_events(|event, _user_data| match event {
+                SurfaceEvent::Scale { scale_factor } => {
+                    cb(XdgPopupEvent::Scale { scale_factor }, self);
+                }
+                SurfaceEvent::PreferredTransform { transform } => {
+                    cb(XdgPopupEvent::PreferredTransform { transform }, self);
+                }
+                SurfaceEvent::Frame { time } => {
+                    cb(XdgPopupEvent::Frame { time }, self);
+                }
+                SurfaceEvent::Presentation {
+                    feedback_id,
+                    feedback,
+                } => {
+                    let event = XdgPopupEvent::Presentation {
+                        feedback_id,
+                        feedback,
+                    };
+                    cb(event, self);
+                }
+                SurfaceEvent::Seat { seat_id, event } => {
+                    cb(XdgPopupEvent::Seat { seat_id, event }, self);
+                }
+                SurfaceEvent::SubsurfaceSeat {
+                    surface_id,
+                    seat_id,
+                    event,
+                } => {
+                    let event = XdgPopupEvent::SubsurfaceSeat {
+                        surface_id,
+                        seat_id,
+                        event,
+                    };
+                    cb(event, self);
+                }
+            });
+        }
+        self.event_drain.poll_events(|event| {
+            cb(event, self);
+        });
+    }
+
+    /// Sets the region that accepts input
+    ///
+    /// `None` accepts input on the whole surface. Takes effect on the next
+    /// commit.
+    pub fn set_input_region(&self, region: Option<&Region>) {
+        self.surface_manager.set_input_region(&self.surface, region);
+    }
+
+    /// Sets the region that is opaque
+    ///
+    /// `None` marks the whole surface as translucent. Takes effect on the
+    /// next commit.
+    pub fn set_opaque_region(&self, region: Option<&Region>) {
+        self.surface_manager
+            .set_opaque_region(&self.surface, region);
+    }
+}
+
+impl Drop for XdgPopup {
+    fn drop(&mut self) {
+        self.xdg_popup.destroy();
+        self.xdg_surface.destroy();
+        self.surface.destroy();
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Possible events generated by a popup that you need to handle
+pub enum XdgPopupEvent {
+    /// The surface scale factor has changed
+    Scale {
+        /// New scale factor
+        scale_factor: u32,
+    },
+    /// The transform the compositor prefers buffers to be rendered with
+    /// has changed
+    PreferredTransform {
+        /// The new preferred transform
+        transform: Transform,
+    },
+    /// It is a good time to draw the next frame
+    Frame {
+        /// A timestamp in milliseconds with undefined base
+        time: u32,
+    },
+    /// Presentation feedback was received
+    Presentation {
+        /// The id returned by `Presentation::feedback`
+        feedback_id: u32,
+        /// How the commit was presented
+        feedback: PresentationFeedback,
+    },
+    /// A seat event was received
+    Seat {
+        /// Seat that sent the event
+        seat_id: u32,
+        /// The sent event
+        event: SeatEvent,
+    },
+    /// A seat event was received by a subsurface
+    SubsurfaceSeat {
+        /// The id of the subsurface's `wl_surface`
+        surface_id: u32,
+        /// Seat that sent the event
+        seat_id: u32,
+        /// The sent event
+        event: SeatEvent,
+    },
+    /// The compositor placed the popup
+    Configure {
+        /// The serial of the configure
+        ///
+        /// It is acknowledged by the next `XdgPopup::commit`, or explicitly
+        /// with `XdgPopup::ack_configure`. Committing the `wl_surface`
+        /// directly leaves it unacknowledged without a warning.
+        serial: u32,
+        /// The position relative to the window geometry of the parent
+        position: (i32, i32),
+        /// The size of the popup
+        size: (u32, u32),
+    },
+    /// The popup was dismissed
+    ///
+    /// For example because the user clicked outside of it. Drop the popup.
+    Done,
+}
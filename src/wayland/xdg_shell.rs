@@ -1,11 +1,23 @@
 //! Handles the `xdg_wm_base` protocol.
+//!
+//! Configures are acknowledged by `XdgShellSurface::commit` or
+//! `XdgShellSurface::ack_configure`. Committing the `wl_surface` directly,
+//! e.g. with `SurfaceRequests::commit`, leaves the configure unacknowledged
+//! without any warning.
+use crate::wayland::damage::Rect;
 use crate::wayland::event_queue::{EventDrain, EventQueue};
-use crate::wayland::seat::SeatEvent;
+use crate::wayland::layer_shell::LayerShellSurface;
+use crate::wayland::output::{Transform, WlOutput};
+use crate::wayland::presentation::PresentationFeedback;
+use crate::wayland::region::Region;
+use crate::wayland::seat::{SeatEvent, SeatManager};
 use crate::wayland::surface::{
     SurfaceEvent, SurfaceManager, SurfaceRequests, SurfaceUserData, WlSurface,
 };
-use std::sync::Mutex;
+use crate::wayland::xdg_popup::{create_popup, Positioner, XdgPopup};
+use std::sync::{Arc, Mutex};
 use wayland_client::{GlobalManager, Proxy};
+pub use wayland_protocols::xdg_shell::client::xdg_toplevel::ResizeEdge;
 use wayland_protocols::xdg_shell::client::{
     xdg_surface::Event as XdgSurfaceEvent_,
     xdg_surface::RequestsTrait as XdgSurfaceRequests, xdg_surface::XdgSurface,
@@ -18,6 +30,7 @@ use wayland_protocols::xdg_shell::client::{
 /// The xdg shell
 pub struct XdgShell {
     surface_manager: SurfaceManager,
+    seat_manager: SeatManager,
     xdg_shell: Proxy<XdgWmBase>,
 }
 
@@ -26,6 +39,7 @@ impl XdgShell {
     pub fn new(
         globals: &GlobalManager,
         surface_manager: SurfaceManager,
+        seat_manager: SeatManager,
     ) -> Self {
         let xdg_shell = globals
             .instantiate_auto(|wm_base| {
@@ -41,27 +55,64 @@ impl XdgShell {
         XdgShell {
             xdg_shell,
             surface_manager,
+            seat_manager,
         }
     }
 
+    /// Creates a `XdgPopup` of `parent`
+    ///
+    /// `parent` is the `xdg_surface` of a `XdgShellSurface` or of another
+    /// `XdgPopup`.
+    pub fn create_popup(
+        &self,
+        parent: &Proxy<XdgSurface>,
+        positioner: &Positioner,
+    ) -> XdgPopup {
+        create_popup(
+            &self.xdg_shell,
+            &self.surface_manager,
+            &self.seat_manager,
+            Some(parent),
+            None,
+            positioner,
+        )
+    }
+
+    /// Creates a `XdgPopup` of a layer shell surface
+    pub fn create_layer_popup(
+        &self,
+        parent: &LayerShellSurface,
+        positioner: &Positioner,
+    ) -> XdgPopup {
+        create_popup(
+            &self.xdg_shell,
+            &self.surface_manager,
+            &self.seat_manager,
+            None,
+            Some(parent.xdg_surface()),
+            positioner,
+        )
+    }
+
     /// Creates a `XdgShellSurface`
     pub fn create_shell_surface(&self) -> XdgShellSurface {
         let (source, drain) = EventQueue::new();
         let surface = self.surface_manager.create_surface();
-        let xdg_surface = self
-            .xdg_shell
-            .get_xdg_surface(&surface, |xdg_surface| {
-                xdg_surface.implement(
-                    |event, xdg_surface| match event {
-                        XdgSurfaceEvent_::Configure { serial } => {
-                            xdg_surface.ack_configure(serial);
-                        }
-                    },
-                    (),
-                )
-            })
-            .unwrap();
+        // the toplevel state is sent before the configure it belongs to
+        let pending = Arc::new(Mutex::new((None, Vec::new())));
+        let source2 = source.clone();
+        let pending2 = pending.clone();
+        let xdg_surface =
+            XdgSurfaceHandle::new(&self.xdg_shell, &surface, move |serial| {
+                let (size, states) = pending2.lock().unwrap().clone();
+                source2.push_event(XdgSurfaceEvent::Configure {
+                    serial,
+                    size,
+                    states,
+                });
+            });
         let xdg_toplevel = xdg_surface
+            .xdg_surface()
             .get_toplevel(|xdg_toplevel| {
                 xdg_toplevel.implement(
                     move |event, _xdg_toplevel| match event {
@@ -82,21 +133,12 @@ impl XdgShell {
                             } else {
                                 Some((width, height))
                             };
-                            let view: &[u32] = unsafe {
-                                ::std::slice::from_raw_parts(
-                                    states.as_ptr() as *const _,
-                                    states.len() / 4,
-                                )
-                            };
-                            let states = view
+                            let states = u32_array(&states)
                                 .iter()
                                 .cloned()
                                 .flat_map(State::from_raw)
                                 .collect::<Vec<_>>();
-                            source.push_event(XdgSurfaceEvent::Configure {
-                                size,
-                                states,
-                            });
+                            *pending.lock().unwrap() = (size, states);
                         }
                     },
                     (),
@@ -105,6 +147,8 @@ impl XdgShell {
             .unwrap();
         surface.commit();
         XdgShellSurface {
+            surface_manager: self.surface_manager.clone(),
+            seat_manager: self.seat_manager.clone(),
             surface,
             xdg_surface,
             xdg_toplevel,
@@ -113,10 +157,145 @@ impl XdgShell {
     }
 }
 
+/// Finds the edge of a window under the pointer
+///
+/// `position` is the pointer position relative to the window geometry of
+/// `size`. Returns `ResizeEdge::None` if the pointer is more than `border`
+/// away from the edges.
+pub fn resize_edge(
+    position: (f64, f64),
+    size: (u32, u32),
+    border: f64,
+) -> ResizeEdge {
+    let (x, y) = position;
+    let (width, height) = (f64::from(size.0), f64::from(size.1));
+    let left = x < border;
+    let right = x >= width - border;
+    let top = y < border;
+    let bottom = y >= height - border;
+    match (left, right, top, bottom) {
+        (true, _, true, _) => ResizeEdge::TopLeft,
+        (_, true, true, _) => ResizeEdge::TopRight,
+        (true, _, _, true) => ResizeEdge::BottomLeft,
+        (_, true, _, true) => ResizeEdge::BottomRight,
+        (true, _, _, _) => ResizeEdge::Left,
+        (_, true, _, _) => ResizeEdge::Right,
+        (_, _, true, _) => ResizeEdge::Top,
+        (_, _, _, true) => ResizeEdge::Bottom,
+        _ => ResizeEdge::None,
+    }
+}
+
+/// The name of the cursor showing that `edge` can be dragged
+///
+/// Pass it to `Cursor::change_cursor`.
+pub fn resize_edge_cursor(edge: ResizeEdge) -> &'static str {
+    match edge {
+        ResizeEdge::Top => "top_side",
+        ResizeEdge::Bottom => "bottom_side",
+        ResizeEdge::Left => "left_side",
+        ResizeEdge::Right => "right_side",
+        ResizeEdge::TopLeft => "top_left_corner",
+        ResizeEdge::TopRight => "top_right_corner",
+        ResizeEdge::BottomLeft => "bottom_left_corner",
+        ResizeEdge::BottomRight => "bottom_right_corner",
+        _ => "left_ptr",
+    }
+}
+
+/// Reinterprets a `wl_array` of 32 bit values
+fn u32_array(array: &[u8]) -> &[u32] {
+    unsafe {
+        ::std::slice::from_raw_parts(
+            array.as_ptr() as *const _,
+            array.len() / 4,
+        )
+    }
+}
+
+/// A `xdg_surface` that acknowledges its last configure on commit
+///
+/// Shared by `XdgShellSurface` and `XdgPopup`.
+pub(crate) struct XdgSurfaceHandle {
+    surface: Proxy<WlSurface>,
+    xdg_surface: Proxy<XdgSurface>,
+    /// The serial of the last configure that wasn't acknowledged
+    unacked: Arc<Mutex<Option<u32>>>,
+}
+
+impl XdgSurfaceHandle {
+    /// Creates the `xdg_surface` of `surface`
+    ///
+    /// `on_configure` is called with the serial of every configure.
+    pub(crate) fn new<F>(
+        xdg_shell: &Proxy<XdgWmBase>,
+        surface: &Proxy<WlSurface>,
+        mut on_configure: F,
+    ) -> Self
+    where
+        F: FnMut(u32) + Send + 'static,
+    {
+        let unacked = Arc::new(Mutex::new(None));
+        let unacked2 = unacked.clone();
+        let xdg_surface = xdg_shell
+            .get_xdg_surface(surface, |xdg_surface| {
+                xdg_surface.implement(
+                    move |event, _xdg_surface| match event {
+                        XdgSurfaceEvent_::Configure { serial } => {
+                            *unacked2.lock().unwrap() = Some(serial);
+                            on_configure(serial);
+                        }
+                    },
+                    (),
+                )
+            })
+            .unwrap();
+        XdgSurfaceHandle {
+            surface: surface.clone(),
+            xdg_surface,
+            unacked,
+        }
+    }
+
+    pub(crate) fn xdg_surface(&self) -> &Proxy<XdgSurface> {
+        &self.xdg_surface
+    }
+
+    pub(crate) fn ack_configure(&self, serial: u32) {
+        let mut unacked = self.unacked.lock().unwrap();
+        self.xdg_surface.ack_configure(serial);
+        if *unacked == Some(serial) {
+            *unacked = None;
+        }
+    }
+
+    pub(crate) fn commit(&self) {
+        if let Some(serial) = self.unacked.lock().unwrap().take() {
+            self.xdg_surface.ack_configure(serial);
+        }
+        self.surface.commit();
+    }
+
+    pub(crate) fn set_window_geometry(&self, geometry: Rect) {
+        self.xdg_surface.set_window_geometry(
+            geometry.x,
+            geometry.y,
+            geometry.width,
+            geometry.height,
+        );
+    }
+
+    pub(crate) fn destroy(&self) {
+        self.xdg_surface.destroy();
+    }
+}
+
 /// A xdg shell surface
 pub struct XdgShellSurface {
+    surface_manager: SurfaceManager,
+    seat_manager: SeatManager,
     surface: Proxy<WlSurface>,
-    xdg_surface: Proxy<XdgSurface>,
+    xdg_surface: XdgSurfaceHandle,
     xdg_toplevel: Proxy<XdgToplevel>,
     event_drain: EventDrain<XdgSurfaceEvent>,
 }
@@ -129,7 +308,7 @@ impl XdgShellSurface {
 
     /// Returns the `xdg_surface`
     pub fn xdg_surface(&self) -> &Proxy<XdgSurface> {
-        &self.xdg_surface
+        self.xdg_surface.xdg_surface()
     }
 
     /// Returns the `xdg_toplevel`
@@ -153,9 +332,37 @@ impl XdgShellSurface {
                 SurfaceEvent::Scale { scale_factor } => {
                     cb(XdgSurfaceEvent::Scale { scale_factor }, self);
                 }
+                SurfaceEvent::PreferredTransform { transform } => {
+                    cb(XdgSurfaceEvent::PreferredTransform { transform }, self);
+                }
+                SurfaceEvent::Frame { time } => {
+                    cb(XdgSurfaceEvent::Frame { time }, self);
+                }
+                SurfaceEvent::Presentation {
+                    feedback_id,
+                    feedback,
+                } => {
+                    let event = XdgSurfaceEvent::Presentation {
+                        feedback_id,
+                        feedback,
+                    };
+                    cb(event, self);
+                }
                 SurfaceEvent::Seat { seat_id, event } => {
                     cb(XdgSurfaceEvent::Seat { seat_id, event }, self);
                 }
+                SurfaceEvent::SubsurfaceSeat {
+                    surface_id,
+                    seat_id,
+                    event,
+                } => {
+                    let event = XdgSurfaceEvent::SubsurfaceSeat {
+                        surface_id,
+                        seat_id,
+                        event,
+                    };
+                    cb(event, self);
+                }
             });
         }
         self.event_drain.poll_events(|event| {
@@ -163,6 +370,48 @@ impl XdgShellSurface {
         });
     }
 
+    /// Acknowledges the configure with `serial`
+    ///
+    /// The next commit applies the state of that configure, so draw the
+    /// matching buffer before committing.
+    pub fn ack_configure(&self, serial: u32) {
+        self.xdg_surface.ack_configure(serial);
+    }
+
+    /// Commits the surface
+    ///
+    /// Acknowledges the last configure first if that wasn't done with
+    /// `ack_configure`. Use it instead of committing the `wl_surface`.
+    pub fn commit(&self) {
+        self.xdg_surface.commit();
+    }
+
+    /// Sets the part of the surface that is the window
+    ///
+    /// Everything outside of `geometry`, like client-side shadows, is
+    /// ignored when the window is placed, resized or snapped. Takes effect
+    /// on the next commit.
+    pub fn set_window_geometry(&self, geometry: Rect) {
+        self.xdg_surface.set_window_geometry(geometry);
+    }
+
+    /// Sets the region that accepts input
+    ///
+    /// `None` accepts input on the whole surface, an empty region makes
+    /// the surface click-through. Takes effect on the next commit.
+    pub fn set_input_region(&self, region: Option<&Region>) {
+        self.surface_manager.set_input_region(&self.surface, region);
+    }
+
+    /// Sets the region that is opaque
+    ///
+    /// `None` marks the whole surface as translucent. Takes effect on the
+    /// next commit.
+    pub fn set_opaque_region(&self, region: Option<&Region>) {
+        self.surface_manager
+            .set_opaque_region(&self.surface, region);
+    }
+
     /// Set the app id
     pub fn set_app_id(&self, app_id: String) {
         self.xdg_toplevel.set_app_id(app_id);
@@ -172,6 +421,105 @@ impl XdgShellSurface {
     pub fn set_title(&self, title: String) {
         self.xdg_toplevel.set_title(title);
     }
+
+    /// Sets the minimum size of the window geometry
+    ///
+    /// `(0, 0)` removes the limit. Takes effect on the next commit.
+    pub fn set_min_size(&self, width: i32, height: i32) {
+        self.xdg_toplevel.set_min_size(width, height);
+    }
+
+    /// Sets the maximum size of the window geometry
+    ///
+    /// `(0, 0)` removes the limit. Takes effect on the next commit.
+    pub fn set_max_size(&self, width: i32, height: i32) {
+        self.xdg_toplevel.set_max_size(width, height);
+    }
+
+    /// Requests the window to be maximized
+    ///
+    /// The compositor answers with a `Configure` containing the
+    /// `Maximized` state.
+    pub fn set_maximized(&self) {
+        self.xdg_toplevel.set_maximized();
+    }
+
+    /// Requests the window to be unmaximized
+    pub fn unset_maximized(&self) {
+        self.xdg_toplevel.unset_maximized();
+    }
+
+    /// Requests the window to be fullscreen on `output`
+    ///
+    /// With `None` the compositor chooses the output.
+    pub fn set_fullscreen(&self, output: Option<&Proxy<WlOutput>>) {
+        self.xdg_toplevel.set_fullscreen(output);
+    }
+
+    /// Requests the window to leave fullscreen
+    pub fn unset_fullscreen(&self) {
+        self.xdg_toplevel.unset_fullscreen();
+    }
+
+    /// Requests the window to be minimized
+    ///
+    /// There is no event telling if the window was minimized.
+    pub fn set_minimized(&self) {
+        self.xdg_toplevel.set_minimized();
+    }
+
+    /// Makes the window a dialog of `parent`
+    ///
+    /// `None` makes it a top level window again.
+    pub fn set_parent(&self, parent: Option<&XdgShellSurface>) {
+        self.xdg_toplevel
+            .set_parent(parent.map(|parent| &parent.xdg_toplevel));
+    }
+
+    /// Starts an interactive move of the window
+    ///
+    /// `serial` is the serial of the pointer button or touch down event
+    /// that started the move.
+    pub fn start_move(&self, seat_id: u32, serial: u32) {
+        match self.seat_manager.get_seat(seat_id) {
+            Some(seat) => self.xdg_toplevel._move(seat.proxy(), serial),
+            None => {
+                eprintln!("[SCTK] XdgShellSurface: unknown seat {}", seat_id)
+            }
+        }
+    }
+
+    /// Starts an interactive resize of the window at `edge`
+    ///
+    /// `serial` is the serial of the pointer button or touch down event
+    /// that started the resize. Use `resize_edge` to find the edge.
+    pub fn start_resize(&self, seat_id: u32, serial: u32, edge: ResizeEdge) {
+        match self.seat_manager.get_seat(seat_id) {
+            Some(seat) => {
+                self.xdg_toplevel
+                    .resize(seat.proxy(), serial, edge.to_raw())
+            }
+            None => {
+                eprintln!("[SCTK] XdgShellSurface: unknown seat {}", seat_id)
+            }
+        }
+    }
+
+    /// Shows the window menu of the compositor
+    ///
+    /// `serial` is the serial of the user input that opened the menu and
+    /// `(x, y)` the position relative to the window geometry.
+    pub fn show_window_menu(&self, seat_id: u32, serial: u32, x: i32, y: i32) {
+        match self.seat_manager.get_seat(seat_id) {
+            Some(seat) => {
+                self.xdg_toplevel
+                    .show_window_menu(seat.proxy(), serial, x, y)
+            }
+            None => {
+                eprintln!("[SCTK] XdgShellSurface: unknown seat {}", seat_id)
+            }
+        }
+    }
 }
 
 impl Drop for XdgShellSurface {
@@ -190,6 +538,24 @@ pub enum XdgSurfaceEvent {
         /// New scale factor
         scale_factor: u32,
     },
+    /// The transform the compositor prefers buffers to be rendered with
+    /// has changed
+    PreferredTransform {
+        /// The new preferred transform
+        transform: Transform,
+    },
+    /// It is a good time to draw the next frame
+    Frame {
+        /// A timestamp in milliseconds with undefined base
+        time: u32,
+    },
+    /// Presentation feedback was received
+    Presentation {
+        /// The id returned by `Presentation::feedback`
+        feedback_id: u32,
+        /// How the commit was presented
+        feedback: PresentationFeedback,
+    },
     /// A seat event was received
     Seat {
         /// Seat that sent the event
@@ -197,8 +563,23 @@ pub enum XdgSurfaceEvent {
         /// The sent event
         event: SeatEvent,
     },
+    /// A seat event was received by a subsurface
+    SubsurfaceSeat {
+        /// The id of the subsurface's `wl_surface`
+        surface_id: u32,
+        /// Seat that sent the event
+        seat_id: u32,
+        /// The sent event
+        event: SeatEvent,
+    },
     /// The state of your window has been changed
     Configure {
+        /// The serial of the configure
+        ///
+        /// It is acknowledged by the next `XdgShellSurface::commit`, or
+        /// explicitly with `XdgShellSurface::ack_configure`. Committing the
+        /// `wl_surface` directly leaves it unacknowledged without a warning.
+        serial: u32,
         /// Optional new size for your shell surface
         ///
         /// This is the new size of the contents of your shell surface
@@ -1,6 +1,8 @@
 //! Uses `xkbcommon` to keep track of keyboard state
 use crate::locale::get_locale_ctype;
-use std::os::unix::io::RawFd;
+use memmap::MmapOptions;
+use std::fs::File;
+use std::os::unix::io::{FromRawFd, RawFd};
 use xkbcommon::xkb::compose::{FeedResult, Status as ComposeStatus};
 use xkbcommon::xkb::compose::{State as ComposeState, Table as ComposeTable};
 use xkbcommon::xkb::compose::{COMPILE_NO_FLAGS, STATE_NO_FLAGS};
@@ -44,11 +46,28 @@ impl KeyboardState {
     }
 
     /// Loads a keymap from a file descriptor
+    ///
+    /// The file is mapped `MAP_PRIVATE` and `fd` is closed afterwards. The
+    /// previous keymap is kept if the file can't be mapped.
     pub fn load_keymap_from_fd(&mut self, fd: RawFd, size: usize) {
-        let keymap = Keymap::new_from_fd(
+        let file = unsafe { File::from_raw_fd(fd) };
+        let map = unsafe { MmapOptions::new().len(size).map_copy(&file) };
+        let map = match map {
+            Ok(map) => map,
+            Err(err) => {
+                eprintln!(
+                    "[SCTK] KeyboardState: failed to map keymap: {}",
+                    err
+                );
+                return;
+            }
+        };
+        // the keymap string is nul terminated
+        let len = map.iter().position(|b| *b == 0).unwrap_or(size);
+        let string = String::from_utf8_lossy(&map[..len]).into_owned();
+        let keymap = Keymap::new_from_string(
             &self.context,
-            fd,
-            size,
+            string,
             KEYMAP_FORMAT_TEXT_V1,
             KEYMAP_COMPILE_NO_FLAGS,
         )